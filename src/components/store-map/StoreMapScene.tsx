@@ -19,6 +19,7 @@ interface StoreMapSceneProps {
   products?: Product[]
   activeCommand?: RobotTask | null
   onCommandComplete?: (commandId: string) => void
+  onCommandFailed?: (commandId: string) => void
   onTrackedRobotUpdate?: (robot: Robot | undefined) => void
   rowConfig?: RowConfig
   followTrackedRobot?: boolean
@@ -136,6 +137,7 @@ export const StoreMapScene = ({
   products,
   activeCommand,
   onCommandComplete,
+  onCommandFailed,
   onTrackedRobotUpdate,
   rowConfig = DEFAULT_ROW_CONFIG,
   followTrackedRobot = false
@@ -153,7 +155,8 @@ export const StoreMapScene = ({
     productsToUse,
     activeCommand,
     onCommandComplete,
-    rowConfig
+    rowConfig,
+    onCommandFailed
   )
   const trackedRobot = useMemo(
     () => robots.find((robot) => robot.id === trackedRobotId),
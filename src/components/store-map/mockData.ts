@@ -3,9 +3,12 @@ import { DEFAULT_ROW_CONFIG } from './types'
 
 const STORE_WIDTH = 250
 const STORE_HEIGHT = 150
+// Must match LAYOUT_VERSION in wasm/src/layout.rs; other versions are rejected
+export const BUFFER_LAYOUT_VERSION = 2
 
 export function rowConfigToBuffer(config: RowConfig): Float32Array {
   return new Float32Array([
+    BUFFER_LAYOUT_VERSION,
     config.storeWidth,
     config.storeHeight,
     config.count,
@@ -131,7 +134,8 @@ export const generateRobots = (
       speed:
         MIN_ROBOT_SPEED + Math.random() * (MAX_ROBOT_SPEED - MIN_ROBOT_SPEED),
       destX: destPos.x,
-      destY: destPos.y
+      destY: destPos.y,
+      battery: 1
     })
   }
 
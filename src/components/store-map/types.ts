@@ -15,6 +15,7 @@ export interface Robot {
   destX: number
   destY: number
   lastMoveTime?: number // Track last time robot moved
+  battery?: number // 0 empty, 1 full
   carryingProductId?: string
   task?: RobotTask
 }
@@ -26,11 +27,10 @@ export interface RobotTask {
   robotId: string
   productId: string
   dropTarget: { x: number; y: number }
+  pickupTarget?: { x: number; y: number } // Set once when the task is assigned
   phase: RobotTaskPhase
   issuedAt: number
   waypoints?: { x: number; y: number }[]
-  waypointIndex?: number
-  waypointsTarget?: string
 }
 
 export interface RowConfig {
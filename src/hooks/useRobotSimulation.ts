@@ -1,7 +1,7 @@
-import { useEffect, useRef, useState } from 'react'
+import { useEffect, useMemo, useRef, useState } from 'react'
 import {
+  BUFFER_LAYOUT_VERSION,
   getRowCenterCoord,
-  MAX_ROBOT_SPEED,
   rowConfigToBuffer,
   transformPosition
 } from '@/components/store-map/mockData'
@@ -13,10 +13,38 @@ import type {
   RowConfig
 } from '@/components/store-map/types'
 import { DEFAULT_ROW_CONFIG } from '@/components/store-map/types'
-import { loadWasm } from '@/wasm/loadWasm'
+import {
+  type BufferLayout,
+  loadWasm,
+  type SimulationHandle
+} from '@/wasm/loadWasm'
 
 const UPDATE_INTERVAL = 50
 const IDLE_MARGIN = 15
+// 8-connected paths cut diagonally across open walkways instead of stair-stepping
+const PATH_CONNECTIVITY = 8
+// Keep only the waypoints needed for line of sight so robots drive straight segments
+const SMOOTH_PATHS = true
+// Index order matches the variant argument of `Simulation.add_robot`
+const ROBOT_VARIANTS: Robot['variant'][] = ['walking', 'tracked', 'dome']
+
+type Point = { x: number; y: number }
+
+// One simulation per store layout, plus what has been loaded into it
+type SimulationState = {
+  sim: SimulationHandle
+  // App robot id to simulation robot id
+  ids: Map<string, number>
+  // App robot id to the task leg (`${taskId}-${phase}`) its path is for
+  routes: Map<string, string>
+  // Commands dropped because their product cannot be reached
+  skipped: Set<string>
+  // Robots and product buffer the simulation currently holds
+  robots: Robot[] | null
+  products: Float32Array | null
+  // View over the robot states in wasm memory
+  states: Float32Array | null
+}
 
 function getIdleDestination(config: RowConfig) {
   const numWalkways = Math.max(1, config.count - 1)
@@ -31,44 +59,36 @@ function getIdleDestination(config: RowConfig) {
   return transformPosition(x, y, config.orientation)
 }
 
-function toStorePosition(
-  position: { x: number; y: number },
-  orientation: RowConfig['orientation']
-) {
-  return orientation === 'horizontal'
-    ? { x: position.y, y: position.x }
-    : position
-}
-
-function getPickupTarget(product: Product, config: RowConfig) {
-  const storePos = toStorePosition(product, config.orientation)
-  const rawIndex = Math.round(
-    (storePos.x + config.storeWidth / 2 - config.startOffset) / config.spacing
+// Closest reachable walkway cell beside the product's shelf, chosen on the nav grid.
+// Both shelf faces are tried, so null means the robot cannot get to either side.
+function getPickupTarget(
+  sim: SimulationHandle,
+  robotId: number,
+  productIndex: number
+): Point | null {
+  const result = sim.pickup_pose_for_product(
+    robotId,
+    productIndex,
+    PATH_CONNECTIVITY
   )
-  const rowIndex = Math.min(Math.max(rawIndex, 0), config.count - 1)
-  const rowCenter = getRowCenterCoord(rowIndex, config)
-  const offset = config.thickness / 2 + 2
-  const direction = rowIndex < config.count - 1 ? 1 : -1
-  let x = rowCenter + direction * offset
-  const minX = -config.storeWidth / 2 + 5
-  const maxX = config.storeWidth / 2 - 5
-  x = Math.min(Math.max(x, minX), maxX)
-  return transformPosition(x, storePos.y, config.orientation)
+  // [reachable, x, y, orientation]
+  if (result[0] !== 1) return null
+  return { x: result[1], y: result[2] }
 }
 
-function flattenRobots(robots: Robot[]) {
-  const data = new Float32Array(robots.length * 7)
-  robots.forEach((robot, idx) => {
-    const offset = idx * 7
-    data[offset] = robot.x
-    data[offset + 1] = robot.y
-    data[offset + 2] = robot.destX
-    data[offset + 3] = robot.destY
-    data[offset + 4] = robot.orientation
-    data[offset + 5] = robot.speed
-    data[offset + 6] = robot.lastMoveTime ?? 0
-  })
-  return data
+function assertBufferLayouts(
+  wasmModule: Awaited<ReturnType<typeof loadWasm>>
+) {
+  for (const layout of [
+    wasmModule.robotBufferLayout(),
+    wasmModule.configBufferLayout()
+  ]) {
+    if (layout.version !== BUFFER_LAYOUT_VERSION) {
+      throw new Error(
+        `WASM ${layout.name} buffer layout is version ${layout.version}, expected ${BUFFER_LAYOUT_VERSION}`
+      )
+    }
+  }
 }
 
 function flattenProducts(products: Product[]) {
@@ -81,356 +101,289 @@ function flattenProducts(products: Product[]) {
   return data
 }
 
-function inflateRobots(robots: Robot[], updated: Float32Array | number[]) {
+function inflateRobots(
+  robots: Robot[],
+  updated: Float32Array | number[],
+  layout: BufferLayout
+) {
   const arr =
     updated instanceof Float32Array ? updated : new Float32Array(updated)
   const next: Robot[] = []
 
   for (let i = 0; i < robots.length; i++) {
-    const offset = i * 7
-    next.push({
-      ...robots[i],
-      x: arr[offset],
-      y: arr[offset + 1],
-      destX: arr[offset + 2],
-      destY: arr[offset + 3],
-      orientation: arr[offset + 4],
-      speed: arr[offset + 5],
-      lastMoveTime: arr[offset + 6]
+    const offset = layout.headerLength + i * layout.stride
+    const robot: Robot = { ...robots[i] }
+    layout.fields.forEach((field, fieldIdx) => {
+      ;(robot as unknown as Record<string, number>)[field] =
+        arr[offset + fieldIdx]
     })
+    next.push(robot)
   }
 
   return next
 }
 
+// Robot states read in place from wasm memory. The view is rebuilt only when
+// the fleet size changes (the states move) or memory grows (the old buffer is
+// detached and `memory.buffer` is a new object).
+function robotStatesView(state: SimulationState, memory: WebAssembly.Memory) {
+  const ptr = state.sim.robot_states_ptr()
+  const len = state.sim.robot_states_len()
+  let view = state.states
+  if (
+    !view ||
+    view.buffer !== memory.buffer ||
+    view.byteOffset !== ptr ||
+    view.length !== len
+  ) {
+    view = new Float32Array(memory.buffer, ptr, len)
+    state.states = view
+  }
+  return view
+}
+
+// Replace the simulated fleet with `robots`, in order, so `robot_states` lines
+// up with the array
+function loadRobots(state: SimulationState, robots: Robot[]) {
+  for (const id of state.ids.values()) {
+    state.sim.remove_robot(id)
+  }
+  state.ids.clear()
+  state.routes.clear()
+  for (const robot of robots) {
+    const id = state.sim.add_robot(
+      robot.x,
+      robot.y,
+      robot.destX,
+      robot.destY,
+      robot.orientation,
+      robot.speed,
+      Math.max(0, ROBOT_VARIANTS.indexOf(robot.variant))
+    )
+    state.sim.set_robot_battery(id, robot.battery ?? 1)
+    state.ids.set(robot.id, id)
+  }
+  state.robots = robots
+}
+
 function computePath(
-  wasmModule: Awaited<ReturnType<typeof loadWasm>>,
+  sim: SimulationHandle,
   robot: Robot,
-  target: { x: number; y: number },
-  preferOuterWalkway: boolean,
-  config: RowConfig
-): { x: number; y: number }[] {
-  const start = new Float32Array([robot.x, robot.y])
-  const end = new Float32Array([target.x, target.y])
-  const configBuffer = rowConfigToBuffer(config)
-  const result = wasmModule.computePath(
-    start,
-    end,
-    configBuffer,
-    preferOuterWalkway
+  target: Point,
+  preferOuterWalkway: boolean
+): Point[] {
+  const result = sim.compute_path(
+    new Float32Array([robot.x, robot.y]),
+    new Float32Array([target.x, target.y]),
+    preferOuterWalkway,
+    PATH_CONNECTIVITY,
+    SMOOTH_PATHS
   )
   const arr =
     result instanceof Float32Array ? result : new Float32Array(result ?? [])
-  if (arr.length % 2 !== 0 || arr.length === 0) {
+  // [reachable, x1, y1, ...]; unreachable goals come back as a straight-line fallback
+  if (arr.length % 2 !== 1 || arr[0] !== 1) {
     return [{ x: robot.x, y: robot.y }, target]
   }
-  const waypoints: { x: number; y: number }[] = []
-  for (let i = 0; i < arr.length; i += 2) {
+  const waypoints: Point[] = []
+  for (let i = 1; i < arr.length; i += 2) {
     waypoints.push({ x: arr[i], y: arr[i + 1] })
   }
   return waypoints
 }
 
-function moveRobotToWaypoint(
-  wasmModule: Awaited<ReturnType<typeof loadWasm>>,
-  robot: Robot,
-  waypoint: { x: number; y: number },
-  configBuffer: Float32Array,
-  speedOverride?: number
-): Robot {
-  const robotData = new Float32Array([
-    robot.x,
-    robot.y,
-    robot.destX,
-    robot.destY,
-    robot.orientation,
-    speedOverride ?? robot.speed,
-    robot.lastMoveTime ?? 0,
-    waypoint.x,
-    waypoint.y,
-    UPDATE_INTERVAL
-  ])
-  const result = wasmModule.moveRobotToWaypoint(robotData, configBuffer)
-  if (result.length < 3) return robot
-  return {
-    ...robot,
-    x: result[0],
-    y: result[1],
-    orientation: result[2]
+// Hand a path to the simulation; false while the robot is busy charging, in
+// which case the caller tries again on a later tick
+function followPath(sim: SimulationHandle, id: number, waypoints: Point[]) {
+  try {
+    sim.set_robot_path(
+      id,
+      new Float32Array(waypoints.flatMap((point) => [point.x, point.y]))
+    )
+    return true
+  } catch (error) {
+    if ((error as { code?: string }).code === 'robotCharging') return false
+    throw error
   }
 }
 
-function hasArrivedAtWaypoint(
-  wasmModule: Awaited<ReturnType<typeof loadWasm>>,
-  robot: Robot,
-  waypoint: { x: number; y: number },
-  configBuffer: Float32Array
-): boolean {
-  const positions = new Float32Array([robot.x, robot.y, waypoint.x, waypoint.y])
-  const result = wasmModule.hasArrivedAtWaypoint(positions, configBuffer)
-  return result === 1.0
-}
-
 export function useRobotSimulation(
   initialRobots: Robot[],
   products: Product[],
   activeCommand?: RobotTask | null,
   onCommandComplete?: (commandId: string) => void,
-  rowConfig: RowConfig = DEFAULT_ROW_CONFIG
+  rowConfig: RowConfig = DEFAULT_ROW_CONFIG,
+  onCommandFailed?: (commandId: string) => void
 ) {
   const [robots, setRobots] = useState<Robot[]>(initialRobots)
   const [wasmModule, setWasmModule] = useState<Awaited<
     ReturnType<typeof loadWasm>
   > | null>(null)
-  const productLookup = useRef(
-    new Map(products.map((product) => [product.id, product]))
+  // Latest robots, read by the tick outside of React's state updaters because
+  // stepping the simulation must happen exactly once per tick
+  const currentRobots = useRef(initialRobots)
+  const simulation = useRef<SimulationState | null>(null)
+  const productBuffer = useMemo(() => flattenProducts(products), [products])
+  // Simulation product ids are positions in the product buffer
+  const productIndex = useMemo(
+    () => new Map(products.map((product, idx) => [product.id, idx])),
+    [products]
   )
 
-  useEffect(() => {
-    productLookup.current = new Map(
-      products.map((product) => [product.id, product])
-    )
-  }, [products])
-
   useEffect(() => {
     loadWasm()
-      .then(setWasmModule)
+      .then((module) => {
+        assertBufferLayouts(module)
+        setWasmModule(module)
+      })
       .catch((error: Error) => {
         console.error('Failed to load WASM module', error)
         throw new Error('WASM module is required for robot simulation')
       })
   }, [])
 
+  // Built once per layout; robots and products are loaded on the next tick
   useEffect(() => {
     if (!wasmModule) return
+    const state: SimulationState = {
+      sim: new wasmModule.Simulation(rowConfigToBuffer(rowConfig)),
+      ids: new Map(),
+      routes: new Map(),
+      skipped: new Set(),
+      robots: null,
+      products: null,
+      states: null
+    }
+    simulation.current = state
+    return () => {
+      simulation.current = null
+      state.sim.free()
+    }
+  }, [rowConfig, wasmModule])
 
-    const intervalId = setInterval(() => {
-      setRobots((currentRobots) => {
-        const commandRobotId = activeCommand?.robotId ?? null
-        const withCommands = activeCommand
-          ? currentRobots.map((robot) => {
-              if (robot.id !== activeCommand.robotId) return robot
-              const existingTask =
-                robot.task?.id === activeCommand.id
-                  ? robot.task
-                  : { ...activeCommand, phase: 'toProduct' as RobotTaskPhase }
-
-              const pickup = productLookup.current.get(existingTask.productId)
-              const target =
-                existingTask.phase === 'toProduct' && pickup
-                  ? getPickupTarget(pickup, rowConfig)
-                  : existingTask.dropTarget
-
-              if (!target) return robot
-
-              const currentTargetKey =
-                existingTask.phase === 'toProduct'
-                  ? `product-${existingTask.productId}`
-                  : `drop-${existingTask.productId}`
-
-              const shouldPlanPath =
-                !existingTask.waypoints ||
-                existingTask.waypointIndex === undefined ||
-                existingTask.waypoints.length === 0 ||
-                existingTask.waypointIndex >= existingTask.waypoints.length ||
-                existingTask.waypointsTarget !== currentTargetKey
-
-              const preferOuterWalkway =
-                existingTask.phase === 'toProduct' ||
-                existingTask.phase === 'toDropoff'
-              const plannedPath = shouldPlanPath
-                ? computePath(
-                    wasmModule,
-                    robot,
-                    target,
-                    preferOuterWalkway,
-                    rowConfig
-                  )
-                : null
-
-              const waypoints = shouldPlanPath
-                ? plannedPath
-                : existingTask.waypoints
-              const waypointIndex = shouldPlanPath
-                ? 0
-                : (existingTask.waypointIndex ?? 0)
-              const waypoint = waypoints?.[waypointIndex] ?? target
-
-              return {
-                ...robot,
-                task: {
-                  ...existingTask,
-                  waypoints,
-                  waypointIndex,
-                  waypointsTarget: currentTargetKey
-                } as RobotTask,
-                destX: waypoint.x,
-                destY: waypoint.y
-              }
-            })
-          : currentRobots
-
-        const autopilotRobots = withCommands.filter(
-          (robot) => robot.id !== commandRobotId
-        )
-
-        let nextRobots = withCommands
-
-        const configBuffer = rowConfigToBuffer(rowConfig)
+  useEffect(() => {
+    if (!wasmModule) return
+    const robotLayout = wasmModule.robotBufferLayout()
 
-        if (autopilotRobots.length > 0) {
-          const robotBuffer = flattenRobots(autopilotRobots)
-          const productBuffer = flattenProducts(products)
-          const result = wasmModule.updateRobots(
-            robotBuffer,
-            productBuffer,
-            configBuffer,
-            UPDATE_INTERVAL
-          )
-          if (result && result.length === robotBuffer.length) {
-            const inflated = inflateRobots(autopilotRobots, result)
-            let inflateIndex = 0
-            nextRobots = withCommands.map((robot) =>
-              robot.id === commandRobotId ? robot : inflated[inflateIndex++]
-            )
-          }
+    const intervalId = setInterval(() => {
+      const state = simulation.current
+      if (!state) return
+      const { sim, ids, routes, skipped } = state
+
+      if (state.products !== productBuffer) {
+        sim.set_products(productBuffer)
+        state.products = productBuffer
+      }
+      if (state.robots !== currentRobots.current) {
+        loadRobots(state, currentRobots.current)
+      }
+
+      // Give the command robot a path whenever its target changes
+      let nextRobots = currentRobots.current.map((robot) => {
+        if (
+          !activeCommand ||
+          robot.id !== activeCommand.robotId ||
+          skipped.has(activeCommand.id)
+        ) {
+          return robot
         }
-
-        if (activeCommand && commandRobotId) {
-          nextRobots = nextRobots.map((robot) => {
-            if (robot.id !== commandRobotId) return robot
-            const waypoints = robot.task?.waypoints
-            const waypointIndex = robot.task?.waypointIndex ?? 0
-            const waypoint = (waypoints && Array.isArray(waypoints)
-              ? waypoints[waypointIndex]
-              : null) ??
-              robot.task?.dropTarget ?? { x: robot.destX, y: robot.destY }
-            if (
-              typeof wasmModule.moveRobotToWaypointWithProducts === 'function'
-            ) {
-              const robotData = new Float32Array([
-                robot.x,
-                robot.y,
-                robot.destX,
-                robot.destY,
-                robot.orientation,
-                MAX_ROBOT_SPEED,
-                robot.lastMoveTime ?? 0,
-                waypoint.x,
-                waypoint.y,
-                UPDATE_INTERVAL
-              ])
-              const productBuffer = flattenProducts(products)
-              const result = wasmModule.moveRobotToWaypointWithProducts(
-                robotData,
-                productBuffer,
-                configBuffer
-              )
-              if (result.length >= 3) {
-                return {
-                  ...robot,
-                  x: result[0],
-                  y: result[1],
-                  orientation: result[2]
-                }
-              }
-            }
-            return moveRobotToWaypoint(
-              wasmModule,
-              robot,
-              waypoint,
-              configBuffer,
-              MAX_ROBOT_SPEED
-            )
-          })
+        const id = ids.get(robot.id)
+        if (id === undefined) return robot
+        const existingTask =
+          robot.task?.id === activeCommand.id
+            ? robot.task
+            : { ...activeCommand, phase: 'toProduct' as RobotTaskPhase }
+        const leg = `${existingTask.id}-${existingTask.phase}`
+        if (routes.get(robot.id) === leg) return robot
+
+        const productIdx = productIndex.get(existingTask.productId)
+        const pickupTarget =
+          existingTask.pickupTarget ??
+          (productIdx !== undefined
+            ? getPickupTarget(sim, id, productIdx)
+            : undefined)
+        if (pickupTarget === null) {
+          // Report once and leave the robot to its current work
+          skipped.add(existingTask.id)
+          onCommandFailed?.(existingTask.id)
+          return robot
         }
+        const target =
+          existingTask.phase === 'toProduct' && pickupTarget
+            ? pickupTarget
+            : existingTask.dropTarget
+        const waypoints = computePath(sim, robot, target, true)
+        if (!followPath(sim, id, waypoints)) return robot
+        routes.set(robot.id, leg)
+        return {
+          ...robot,
+          task: { ...existingTask, pickupTarget, waypoints } as RobotTask
+        }
+      })
 
-        return nextRobots.map((robot) => {
-          if (!robot.task) return robot
-
-          const pickup = productLookup.current.get(robot.task.productId)
-          const target =
-            robot.task.phase === 'toProduct' && pickup
-              ? getPickupTarget(pickup, rowConfig)
-              : robot.task.dropTarget
-
-          if (!target) {
-            return { ...robot, task: undefined, carryingProductId: undefined }
-          }
-
-          const taskWaypoints = robot.task.waypoints
-          const taskWaypointIndex = robot.task.waypointIndex ?? 0
-          const waypoint =
-            (taskWaypoints && Array.isArray(taskWaypoints)
-              ? taskWaypoints[taskWaypointIndex]
-              : null) ?? target
-
-          if (hasArrivedAtWaypoint(wasmModule, robot, waypoint, configBuffer)) {
-            if (
-              robot.task.waypoints &&
-              (robot.task.waypointIndex ?? 0) < robot.task.waypoints.length - 1
-            ) {
-              const nextIndex = (robot.task.waypointIndex ?? 0) + 1
-              const nextWaypoint = robot.task.waypoints[nextIndex]
-              return {
-                ...robot,
-                task: { ...robot.task, waypointIndex: nextIndex } as RobotTask,
-                destX: nextWaypoint.x,
-                destY: nextWaypoint.y
-              }
-            }
-
-            if (robot.task.phase === 'toProduct') {
-              const dropPath = computePath(
-                wasmModule,
-                robot,
-                robot.task.dropTarget,
-                true,
-                rowConfig
-              )
-              const firstDropWaypoint = dropPath[0] ?? robot.task.dropTarget
-              return {
-                ...robot,
-                carryingProductId: robot.task.productId,
-                task: {
-                  ...robot.task,
-                  phase: 'toDropoff' as RobotTaskPhase,
-                  waypoints: dropPath,
-                  waypointIndex: 0,
-                  waypointsTarget: `drop-${robot.task.productId}`
-                },
-                destX: firstDropWaypoint.x,
-                destY: firstDropWaypoint.y
-              }
-            }
-
-            if (robot.task.phase === 'toDropoff') {
-              onCommandComplete?.(robot.task.id)
-              const idleDestination = getIdleDestination(rowConfig)
-              return {
-                ...robot,
-                carryingProductId: undefined,
-                task: undefined,
-                destX: idleDestination.x,
-                destY: idleDestination.y,
-                lastMoveTime: 0
-              }
-            }
-          }
+      sim.step(UPDATE_INTERVAL)
+      const states = wasmModule.memory
+        ? robotStatesView(state, wasmModule.memory)
+        : sim.robot_states()
+      nextRobots = inflateRobots(nextRobots, states, robotLayout)
+
+      // Advance tasks whose robot reached the end of its path
+      nextRobots = nextRobots.map((robot) => {
+        const id = ids.get(robot.id)
+        if (!robot.task || id === undefined || !routes.has(robot.id)) {
+          return robot
+        }
+        const [, , done] = sim.robot_path_progress(id)
+        if (done !== 1) return robot
 
+        if (robot.task.phase === 'toProduct') {
+          const dropPath = computePath(sim, robot, robot.task.dropTarget, true)
+          if (!followPath(sim, id, dropPath)) return robot
+          routes.set(robot.id, `${robot.task.id}-toDropoff`)
           return {
             ...robot,
-            destX: target.x,
-            destY: target.y
+            carryingProductId: robot.task.productId,
+            task: {
+              ...robot.task,
+              phase: 'toDropoff' as RobotTaskPhase,
+              waypoints: dropPath
+            }
           }
-        })
+        }
+
+        onCommandComplete?.(robot.task.id)
+        sim.clear_robot_path(id)
+        routes.delete(robot.id)
+        const idleDestination = getIdleDestination(rowConfig)
+        sim.set_robot_destination(id, idleDestination.x, idleDestination.y)
+        return {
+          ...robot,
+          carryingProductId: undefined,
+          task: undefined,
+          destX: idleDestination.x,
+          destY: idleDestination.y,
+          lastMoveTime: 0
+        }
       })
+
+      state.robots = nextRobots
+      currentRobots.current = nextRobots
+      setRobots(nextRobots)
     }, UPDATE_INTERVAL)
 
     return () => clearInterval(intervalId)
-  }, [activeCommand, onCommandComplete, products, rowConfig, wasmModule])
+  }, [
+    activeCommand,
+    onCommandComplete,
+    onCommandFailed,
+    productBuffer,
+    productIndex,
+    rowConfig,
+    wasmModule
+  ])
 
   useEffect(() => {
+    currentRobots.current = initialRobots
     setRobots(initialRobots)
   }, [initialRobots])
 
@@ -344,6 +344,14 @@ function RobotsMap() {
     setCommandStatus('Command completed.')
   }
 
+  const handleCommandFailed = () => {
+    const productId = activeCommand?.productId
+    setActiveCommand(null)
+    setCommandStatus(
+      `Command cancelled: the robot cannot reach either side of ${productId}'s shelf.`
+    )
+  }
+
   const liveCommandStatus = useMemo(() => {
     if (
       !activeCommand ||
@@ -896,6 +904,7 @@ function RobotsMap() {
         products={products}
         activeCommand={activeCommand}
         onCommandComplete={handleCommandComplete}
+        onCommandFailed={handleCommandFailed}
         onTrackedRobotUpdate={(robot) => setTrackedRobotState(robot ?? null)}
         rowConfig={rowConfig}
       />
@@ -1,4 +1,57 @@
+export type BufferLayout = {
+  name: string
+  version: number
+  headerLength: number
+  stride: number
+  fields: string[]
+}
+
+export type ConfigProblem = {
+  code: string
+  message: string
+}
+
+// The wasm `Simulation` class; methods keep the names wasm-bindgen generates
+export type SimulationHandle = {
+  free: () => void
+  set_products: (products: Float32Array) => void
+  add_robot: (
+    x: number,
+    y: number,
+    destX: number,
+    destY: number,
+    orientation: number,
+    speed: number,
+    variant: number
+  ) => number
+  remove_robot: (id: number) => boolean
+  set_robot_battery: (id: number, level: number) => boolean
+  set_robot_destination: (id: number, destX: number, destY: number) => boolean
+  set_robot_path: (id: number, path: Float32Array) => void
+  clear_robot_path: (id: number) => boolean
+  robot_path_progress: (id: number) => Float32Array | number[]
+  compute_path: (
+    start: Float32Array,
+    end: Float32Array,
+    preferOuterWalkway: boolean,
+    connectivity: number,
+    smooth: boolean
+  ) => Float32Array | number[]
+  pickup_pose_for_product: (
+    robotId: number,
+    productId: number,
+    connectivity: number
+  ) => Float32Array | number[]
+  step: (deltaMs: number) => void
+  robot_states: () => Float32Array | number[]
+  robot_states_ptr: () => number
+  robot_states_len: () => number
+}
+
 export type WasmApi = {
+  Simulation: new (config: Float32Array) => SimulationHandle
+  // Linear memory behind `robot_states_ptr`; absent for the JS stub
+  memory?: WebAssembly.Memory
   magnitudes: (buffer: Float32Array) => Float32Array | number[]
   dotProducts: (a: Float32Array, b: Float32Array) => Float32Array | number[]
   lerpVectors: (
@@ -10,7 +63,10 @@ export type WasmApi = {
     start: Float32Array,
     end: Float32Array,
     config: Float32Array,
-    preferOuterWalkway: boolean
+    products: Float32Array,
+    preferOuterWalkway: boolean,
+    connectivity: number,
+    smooth: boolean
   ) => Float32Array | number[]
   updateRobots: (
     robots: Float32Array,
@@ -31,6 +87,32 @@ export type WasmApi = {
     positions: Float32Array,
     config: Float32Array
   ) => number
+  robotBufferLayout: () => BufferLayout
+  configBufferLayout: () => BufferLayout
+  validateConfig: (config: Float32Array) => ConfigProblem[]
+  allocateTasks: (
+    robots: Float32Array,
+    tasks: Float32Array,
+    config: Float32Array,
+    products: Float32Array,
+    connectivity: number
+  ) => Float32Array | number[]
+  pickupPoseForProduct: (
+    robot: Float32Array,
+    product: Float32Array,
+    config: Float32Array,
+    products: Float32Array,
+    connectivity: number
+  ) => Float32Array | number[]
+  planPickRoute: (
+    robot: Float32Array,
+    productIds: Uint32Array,
+    dropTarget: Float32Array,
+    config: Float32Array,
+    products: Float32Array,
+    connectivity: number
+  ) => Float32Array | number[]
+  setProductsVersion?: (version: number) => void
 }
 
 let wasmModulePromise: Promise<WasmApi> | null = null
@@ -40,7 +122,8 @@ export async function loadWasm(): Promise<WasmApi> {
     wasmModulePromise = import('@/wasm/pkg/three_sim_wasm')
       .then(async (mod) => {
         const wasmMod = mod as unknown as {
-          default?: () => Promise<unknown>
+          default?: () => Promise<{ memory: WebAssembly.Memory }>
+          Simulation: WasmApi['Simulation']
           magnitudes: WasmApi['magnitudes']
           dot_products: WasmApi['dotProducts']
           lerp_vectors: WasmApi['lerpVectors']
@@ -49,13 +132,23 @@ export async function loadWasm(): Promise<WasmApi> {
           move_robot_to_waypoint: WasmApi['moveRobotToWaypoint']
           move_robot_to_waypoint_with_products?: WasmApi['moveRobotToWaypointWithProducts']
           has_arrived_at_waypoint: WasmApi['hasArrivedAtWaypoint']
+          robot_buffer_layout: WasmApi['robotBufferLayout']
+          config_buffer_layout: WasmApi['configBufferLayout']
+          validate_config: WasmApi['validateConfig']
+          allocate_tasks: WasmApi['allocateTasks']
+          pickup_pose_for_product: WasmApi['pickupPoseForProduct']
+          plan_pick_route: WasmApi['planPickRoute']
+          set_products_version?: WasmApi['setProductsVersion']
         }
 
-        if (typeof wasmMod.default === 'function') {
-          await wasmMod.default()
-        }
+        const exports =
+          typeof wasmMod.default === 'function'
+            ? await wasmMod.default()
+            : undefined
 
         return {
+          Simulation: wasmMod.Simulation,
+          memory: exports?.memory,
           magnitudes: wasmMod.magnitudes,
           dotProducts: wasmMod.dot_products,
           lerpVectors: wasmMod.lerp_vectors,
@@ -64,13 +157,21 @@ export async function loadWasm(): Promise<WasmApi> {
           moveRobotToWaypoint: wasmMod.move_robot_to_waypoint,
           moveRobotToWaypointWithProducts:
             wasmMod.move_robot_to_waypoint_with_products,
-          hasArrivedAtWaypoint: wasmMod.has_arrived_at_waypoint
+          hasArrivedAtWaypoint: wasmMod.has_arrived_at_waypoint,
+          robotBufferLayout: wasmMod.robot_buffer_layout,
+          configBufferLayout: wasmMod.config_buffer_layout,
+          validateConfig: wasmMod.validate_config,
+          allocateTasks: wasmMod.allocate_tasks,
+          pickupPoseForProduct: wasmMod.pickup_pose_for_product,
+          planPickRoute: wasmMod.plan_pick_route,
+          setProductsVersion: wasmMod.set_products_version
         }
       })
       .catch(async () => {
         // Fallback to stubbed JS implementation if wasm-pack output is missing
         const stub = (await import('@/wasm/pkg')) as unknown as {
           default?: () => Promise<unknown>
+          Simulation: WasmApi['Simulation']
           magnitudes: WasmApi['magnitudes']
           dotProducts: WasmApi['dotProducts']
           lerpVectors: WasmApi['lerpVectors']
@@ -79,6 +180,13 @@ export async function loadWasm(): Promise<WasmApi> {
           moveRobotToWaypoint: WasmApi['moveRobotToWaypoint']
           moveRobotToWaypointWithProducts?: WasmApi['moveRobotToWaypointWithProducts']
           hasArrivedAtWaypoint: WasmApi['hasArrivedAtWaypoint']
+          robotBufferLayout: WasmApi['robotBufferLayout']
+          configBufferLayout: WasmApi['configBufferLayout']
+          validateConfig: WasmApi['validateConfig']
+          allocateTasks: WasmApi['allocateTasks']
+          pickupPoseForProduct: WasmApi['pickupPoseForProduct']
+          planPickRoute: WasmApi['planPickRoute']
+          setProductsVersion?: WasmApi['setProductsVersion']
         }
 
         if (typeof stub.default === 'function') {
@@ -86,6 +194,7 @@ export async function loadWasm(): Promise<WasmApi> {
         }
 
         return {
+          Simulation: stub.Simulation,
           magnitudes: stub.magnitudes,
           dotProducts: stub.dotProducts,
           lerpVectors: stub.lerpVectors,
@@ -93,7 +202,14 @@ export async function loadWasm(): Promise<WasmApi> {
           updateRobots: stub.updateRobots,
           moveRobotToWaypoint: stub.moveRobotToWaypoint,
           moveRobotToWaypointWithProducts: stub.moveRobotToWaypointWithProducts,
-          hasArrivedAtWaypoint: stub.hasArrivedAtWaypoint
+          hasArrivedAtWaypoint: stub.hasArrivedAtWaypoint,
+          robotBufferLayout: stub.robotBufferLayout,
+          configBufferLayout: stub.configBufferLayout,
+          validateConfig: stub.validateConfig,
+          allocateTasks: stub.allocateTasks,
+          pickupPoseForProduct: stub.pickupPoseForProduct,
+          planPickRoute: stub.planPickRoute,
+          setProductsVersion: stub.setProductsVersion
         }
       })
   }
@@ -0,0 +1,261 @@
+use crate::constants::StoreConfig;
+use crate::grid::NavGrid;
+use crate::pathfinding::*;
+
+/// Cost given to robot/task pairs whose pickup cannot be reached, so the solver only uses them
+/// when nothing else is left; such pairs are dropped from the result
+const UNREACHABLE_COST: f64 = 1.0e9;
+
+/// One robot sent to one task, with the route it should drive to the pickup (store frame)
+#[derive(Clone, Debug)]
+pub struct TaskAssignment {
+    /// Index into the robot list passed to `allocate_tasks`
+    pub robot: usize,
+    /// Index into the task list passed to `allocate_tasks`
+    pub task: usize,
+    /// Nav-grid path length from the robot to the pickup
+    pub cost: f32,
+    pub route: PathResult,
+}
+
+/// Assign idle robots to pending pickups so the total nav-grid travel distance is minimal.
+/// Every robot/pickup pair is costed with A* on `grid`, then solved with the Hungarian algorithm.
+/// With more robots than tasks some robots stay idle, and with more tasks than robots some tasks
+/// wait for the next round; pairs that are unreachable are never assigned.
+/// Results are ordered by robot index.
+pub fn allocate_tasks(
+    robots: &[(f32, f32)],
+    pickups: &[(f32, f32)],
+    grid: &NavGrid,
+    connectivity: Connectivity,
+    search: &mut PathSearch,
+) -> Vec<TaskAssignment> {
+    let options = PathOptions {
+        connectivity,
+        ..PathOptions::default()
+    };
+
+    let mut routes = Vec::with_capacity(robots.len() * pickups.len());
+    let mut costs = Vec::with_capacity(robots.len());
+    for &robot in robots {
+        let mut row = Vec::with_capacity(pickups.len());
+        for &pickup in pickups {
+            let route = search.plan(robot, pickup, grid, options);
+            row.push(if route.reachable {
+                path_length(&route.waypoints) as f64
+            } else {
+                UNREACHABLE_COST
+            });
+            routes.push(route);
+        }
+        costs.push(row);
+    }
+
+    let mut assignments = Vec::new();
+    for (robot, task) in solve_assignment(&costs).into_iter().enumerate() {
+        let Some(task) = task else {
+            continue;
+        };
+        let route = std::mem::take(&mut routes[robot * pickups.len() + task]);
+        if !route.reachable {
+            continue;
+        }
+        assignments.push(TaskAssignment {
+            robot,
+            task,
+            cost: costs[robot][task] as f32,
+            route,
+        });
+    }
+    assignments
+}
+
+/// Minimum-cost assignment of rows to columns (Hungarian algorithm with potentials, O(n²m)).
+/// Returns the column picked for each row; when there are more rows than columns the
+/// surplus rows get `None`.
+pub fn solve_assignment(costs: &[Vec<f64>]) -> Vec<Option<usize>> {
+    let rows = costs.len();
+    let cols = costs.first().map_or(0, Vec::len);
+    if rows == 0 || cols == 0 {
+        return vec![None; rows];
+    }
+    if rows > cols {
+        // The solver needs rows <= cols; solve the transpose and invert it
+        let transposed: Vec<Vec<f64>> = (0..cols)
+            .map(|col| costs.iter().map(|row| row[col]).collect())
+            .collect();
+        let mut result = vec![None; rows];
+        for (col, row) in solve_assignment(&transposed).into_iter().enumerate() {
+            if let Some(row) = row {
+                result[row] = Some(col);
+            }
+        }
+        return result;
+    }
+
+    // 1-based arrays with index 0 as the virtual source, following the classic formulation
+    let mut row_potential = vec![0.0; rows + 1];
+    let mut col_potential = vec![0.0; cols + 1];
+    let mut col_owner = vec![0usize; cols + 1];
+    let mut way = vec![0usize; cols + 1];
+    for row in 1..=rows {
+        col_owner[0] = row;
+        let mut col0 = 0;
+        let mut min_slack = vec![f64::INFINITY; cols + 1];
+        let mut used = vec![false; cols + 1];
+        loop {
+            used[col0] = true;
+            let row0 = col_owner[col0];
+            let mut delta = f64::INFINITY;
+            let mut col1 = 0;
+            for col in 1..=cols {
+                if used[col] {
+                    continue;
+                }
+                let slack = costs[row0 - 1][col - 1] - row_potential[row0] - col_potential[col];
+                if slack < min_slack[col] {
+                    min_slack[col] = slack;
+                    way[col] = col0;
+                }
+                if min_slack[col] < delta {
+                    delta = min_slack[col];
+                    col1 = col;
+                }
+            }
+            for col in 0..=cols {
+                if used[col] {
+                    row_potential[col_owner[col]] += delta;
+                    col_potential[col] -= delta;
+                } else {
+                    min_slack[col] -= delta;
+                }
+            }
+            col0 = col1;
+            if col_owner[col0] == 0 {
+                break;
+            }
+        }
+        loop {
+            let col1 = way[col0];
+            col_owner[col0] = col_owner[col1];
+            col0 = col1;
+            if col0 == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut result = vec![None; rows];
+    for col in 1..=cols {
+        if col_owner[col] != 0 {
+            result[col_owner[col] - 1] = Some(col - 1);
+        }
+    }
+    result
+}
+
+/// Parse packed [x1, y1, x2, y2, ...] world-frame positions into store-frame points
+pub fn unpack_points(points: &[f32], config: &StoreConfig) -> Vec<(f32, f32)> {
+    points
+        .chunks_exact(2)
+        .map(|point| config.transform_coords(point[0], point[1]))
+        .collect()
+}
+
+/// Output per assignment: [robot, task, cost, waypointCount, x1, y1, x2, y2, ...] in world
+/// coordinates, where `robot` is `robot_key(assignment.robot)` (an index or a robot id)
+pub fn pack_task_assignments(
+    assignments: &[TaskAssignment],
+    robot_key: impl Fn(usize) -> f32,
+    config: &StoreConfig,
+) -> Vec<f32> {
+    let mut output = Vec::new();
+    for assignment in assignments {
+        output.extend_from_slice(&[
+            robot_key(assignment.robot),
+            assignment.task as f32,
+            assignment.cost,
+            assignment.route.waypoints.len() as f32,
+        ]);
+        for (x, y) in &assignment.route.waypoints {
+            let (tx, ty) = config.transform_coords(*x, *y);
+            output.extend_from_slice(&[tx, ty]);
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    /// Cheapest total over every way of giving each row a distinct column (or each column a
+    /// distinct row, when there are more rows than columns)
+    fn brute_force_cost(costs: &[Vec<f64>], row: usize, used: &mut Vec<bool>) -> f64 {
+        let rows_left = costs.len() - row;
+        let cols_left = used.iter().filter(|taken| !**taken).count();
+        if rows_left == 0 || cols_left == 0 {
+            return 0.0;
+        }
+        // Rows may only sit out while there are more rows left than free columns
+        let mut best = if rows_left > cols_left {
+            brute_force_cost(costs, row + 1, used)
+        } else {
+            f64::INFINITY
+        };
+        for col in 0..used.len() {
+            if used[col] {
+                continue;
+            }
+            used[col] = true;
+            best = best.min(costs[row][col] + brute_force_cost(costs, row + 1, used));
+            used[col] = false;
+        }
+        best
+    }
+
+    fn assignment_cost(costs: &[Vec<f64>], assignment: &[Option<usize>]) -> f64 {
+        assignment
+            .iter()
+            .enumerate()
+            .filter_map(|(row, col)| col.map(|col| costs[row][col]))
+            .sum()
+    }
+
+    #[test]
+    fn hungarian_matches_brute_force() {
+        let mut rng = Rng::new(3);
+        for (rows, cols) in [
+            (1, 1),
+            (3, 3),
+            (5, 5),
+            (6, 6),
+            (2, 5),
+            (5, 2),
+            (4, 6),
+            (6, 3),
+        ] {
+            for _ in 0..20 {
+                let costs: Vec<Vec<f64>> = (0..rows)
+                    .map(|_| {
+                        (0..cols)
+                            .map(|_| (rng.next_f32() * 100.0).round() as f64)
+                            .collect()
+                    })
+                    .collect();
+                let assignment = solve_assignment(&costs);
+
+                assert_eq!(assignment.len(), rows);
+                assert_eq!(assignment.iter().flatten().count(), rows.min(cols));
+                let mut picked: Vec<_> = assignment.iter().flatten().collect();
+                picked.sort();
+                picked.dedup();
+                assert_eq!(picked.len(), rows.min(cols), "a column was used twice");
+
+                let expected = brute_force_cost(&costs, 0, &mut vec![false; cols]);
+                assert!((assignment_cost(&costs, &assignment) - expected).abs() < 1e-9);
+            }
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::VecDeque;
+
+use crate::error::SimError;
+use crate::grid::NavGrid;
+use crate::pathfinding::*;
+use crate::snapshot::{SnapshotReader, SnapshotWriter};
+
+/// Energy use and charging rates, in fractions of a full battery (1.0)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BatteryModel {
+    /// Charge used per world unit driven
+    pub drain_per_unit: f32,
+    /// Charge used per second while standing still
+    pub idle_drain_per_second: f32,
+    /// Charge gained per second on a charger
+    pub charge_per_second: f32,
+    /// Below this level a robot stops working and heads for a charger
+    pub low_threshold: f32,
+}
+
+impl Default for BatteryModel {
+    /// About 3000 units of driving or an hour of standing on a full charge, two minutes to recharge
+    fn default() -> Self {
+        BatteryModel {
+            drain_per_unit: 1.0 / 3000.0,
+            idle_drain_per_second: 1.0 / 3600.0,
+            charge_per_second: 1.0 / 120.0,
+            low_threshold: 0.2,
+        }
+    }
+}
+
+impl BatteryModel {
+    /// Level after driving `distance` during a tick, or standing still if it did not move
+    pub fn drain(&self, level: f32, distance: f32, delta_ms: f32) -> f32 {
+        let used = if distance > 0.01 {
+            distance * self.drain_per_unit
+        } else {
+            self.idle_drain_per_second * delta_ms / 1000.0
+        };
+        (level - used).max(0.0)
+    }
+
+    /// Level after a tick on a charger
+    pub fn charge(&self, level: f32, delta_ms: f32) -> f32 {
+        (level + self.charge_per_second * delta_ms / 1000.0).min(1.0)
+    }
+
+    pub fn is_low(&self, level: f32) -> bool {
+        level < self.low_threshold
+    }
+}
+
+/// Charging stations (store frame), which robot holds each one, and the robots waiting for
+/// one to free up in arrival order
+#[derive(Clone, Debug, Default)]
+pub struct Chargers {
+    positions: Vec<(f32, f32)>,
+    occupants: Vec<Option<u32>>,
+    queue: VecDeque<u32>,
+}
+
+impl Chargers {
+    pub fn new(positions: Vec<(f32, f32)>) -> Self {
+        Chargers {
+            occupants: vec![None; positions.len()],
+            positions,
+            queue: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn position(&self, charger: usize) -> (f32, f32) {
+        self.positions[charger]
+    }
+
+    pub fn positions_mut(&mut self) -> &mut [(f32, f32)] {
+        &mut self.positions
+    }
+
+    /// Robot holding the charger, whether it is still driving there or already charging
+    pub fn occupant(&self, charger: usize) -> Option<u32> {
+        self.occupants[charger]
+    }
+
+    pub fn queue(&self) -> &VecDeque<u32> {
+        &self.queue
+    }
+
+    /// Reserve a free charger for a robot
+    pub fn claim(&mut self, charger: usize, robot: u32) {
+        debug_assert!(self.occupants[charger].is_none(), "charger already taken");
+        self.occupants[charger] = Some(robot);
+    }
+
+    /// Wait for a charger; robots are served first come, first served
+    pub fn enqueue(&mut self, robot: u32) {
+        if !self.queue.contains(&robot) {
+            self.queue.push_back(robot);
+        }
+    }
+
+    /// Next robot in the queue, if any
+    pub fn dequeue(&mut self) -> Option<u32> {
+        self.queue.pop_front()
+    }
+
+    /// Drop a robot from its charger and the queue, returning the charger it held
+    pub fn release(&mut self, robot: u32) -> Option<usize> {
+        self.queue.retain(|queued| *queued != robot);
+        let charger = self
+            .occupants
+            .iter()
+            .position(|occupant| *occupant == Some(robot))?;
+        self.occupants[charger] = None;
+        Some(charger)
+    }
+
+    /// Positions, occupants (`u32::MAX` when free) and queue, for `Simulation::snapshot`
+    pub fn write(&self, out: &mut SnapshotWriter) {
+        out.count(self.positions.len());
+        for (position, occupant) in self.positions.iter().zip(&self.occupants) {
+            out.point(*position);
+            out.u32(occupant.unwrap_or(u32::MAX));
+        }
+        out.count(self.queue.len());
+        for robot in &self.queue {
+            out.u32(*robot);
+        }
+    }
+
+    pub fn read(input: &mut SnapshotReader) -> Result<Self, SimError> {
+        let count = input.count(12)?;
+        let mut chargers = Chargers::default();
+        for _ in 0..count {
+            chargers.positions.push(input.point()?);
+            let occupant = input.u32()?;
+            chargers
+                .occupants
+                .push((occupant != u32::MAX).then_some(occupant));
+        }
+        let queued = input.count(4)?;
+        for _ in 0..queued {
+            chargers.queue.push_back(input.u32()?);
+        }
+        Ok(chargers)
+    }
+
+    /// Free charger with the shortest reachable A* path from `from`, with that path ending
+    /// exactly on the charger. None when every charger is taken or none of the free ones can
+    /// be reached.
+    pub fn nearest_free(
+        &self,
+        from: (f32, f32),
+        grid: &NavGrid,
+        connectivity: Connectivity,
+        search: &mut PathSearch,
+    ) -> Option<(usize, PathResult)> {
+        let mut best: Option<(usize, PathResult, f32)> = None;
+        for (charger, position) in self.positions.iter().enumerate() {
+            if self.occupants[charger].is_some() {
+                continue;
+            }
+            let mut route = search.find_path(from, *position, grid, connectivity);
+            if !route.reachable {
+                continue;
+            }
+            route.waypoints = append_goal(route.waypoints, *position);
+            let length = path_length(&route.waypoints);
+            if best
+                .as_ref()
+                .is_none_or(|(_, _, best_length)| length < *best_length)
+            {
+                best = Some((charger, route, length));
+            }
+        }
+        best.map(|(charger, route, _)| (charger, route))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::StoreConfig;
+    use crate::grid::build_nav_grid;
+
+    #[test]
+    fn driving_and_standing_drain_and_charging_fills_to_full() {
+        let model = BatteryModel::default();
+        let driven = model.drain(1.0, 300.0, 50.0);
+        assert!((driven - 0.9).abs() < 1e-6);
+        let idle = model.drain(1.0, 0.0, 3_600_000.0);
+        assert!(idle.abs() < 1e-6);
+        assert_eq!(model.drain(0.01, 300.0, 50.0), 0.0);
+
+        assert!((model.charge(0.5, 6_000.0) - 0.55).abs() < 1e-6);
+        assert_eq!(model.charge(0.99, 6_000.0), 1.0);
+        assert!(model.is_low(0.19) && !model.is_low(0.2));
+    }
+
+    #[test]
+    fn the_queue_serves_robots_in_arrival_order() {
+        let mut chargers = Chargers::new(vec![(0.0, 0.0)]);
+        chargers.claim(0, 1);
+        for robot in [2, 3, 2, 4] {
+            chargers.enqueue(robot);
+        }
+        assert!(chargers.queue().iter().eq(&[2, 3, 4]));
+
+        // A queued robot that goes away gives up its place
+        assert_eq!(chargers.release(3), None);
+        assert_eq!(chargers.release(1), Some(0));
+        assert_eq!(chargers.occupant(0), None);
+        assert_eq!(chargers.dequeue(), Some(2));
+        assert_eq!(chargers.dequeue(), Some(4));
+        assert_eq!(chargers.dequeue(), None);
+    }
+
+    #[test]
+    fn robots_are_routed_to_the_nearest_free_charger() {
+        let grid = build_nav_grid(&StoreConfig::from_buffer(&[]));
+        let mut search = PathSearch::new();
+        let mut chargers = Chargers::new(vec![(-45.0, 50.0), (-45.0, -50.0), (-45.0, -15.0)]);
+        let from = (-45.0, -40.0);
+
+        let (charger, route) = chargers
+            .nearest_free(from, &grid, Connectivity::Eight, &mut search)
+            .unwrap();
+        assert_eq!(charger, 1);
+        assert_eq!(route.waypoints.last(), Some(&(-45.0, -50.0)));
+
+        chargers.claim(1, 7);
+        chargers.claim(2, 8);
+        let (charger, _) = chargers
+            .nearest_free(from, &grid, Connectivity::Eight, &mut search)
+            .unwrap();
+        assert_eq!(charger, 0);
+
+        chargers.claim(0, 9);
+        assert!(
+            chargers
+                .nearest_free(from, &grid, Connectivity::Eight, &mut search)
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,334 @@
+//! Headless store simulator: runs `Simulation` ticks without a browser and writes
+//! trajectories, events, per-robot metrics and a traffic heatmap to disk, then prints fleet
+//! totals.
+//!
+//! ```text
+//! three-sim [--config FILE] [--products FILE | --product-count N] [--robots N]
+//!           [--seconds S] [--tick-ms MS] [--seed N] [--out DIR]
+//! ```
+
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use three_sim_wasm::constants::StoreConfig;
+use three_sim_wasm::events::EVENT_STRIDE;
+use three_sim_wasm::heatmap::HEATMAP_HEADER_LEN;
+use three_sim_wasm::layout::{HEADER_LEN, ROBOT_STRIDE};
+use three_sim_wasm::rng::Rng;
+use three_sim_wasm::simulation::Simulation;
+
+const DEFAULT_CONFIG: [f32; 10] = [250.0, 150.0, 6.0, 40.0, 6.0, 20.0, 10.0, 4.0, 12.0, 0.0];
+const MIN_ROBOT_SPEED: f32 = 2.0;
+const MAX_ROBOT_SPEED: f32 = 5.0;
+
+const USAGE: &str = "usage: three-sim [--config FILE] [--products FILE | --product-count N] \
+[--robots N] [--seconds S] [--tick-ms MS] [--seed N] [--out DIR]
+
+  --config FILE        config buffer as the app sends it: layout version, then 10 floats
+  --products FILE      product positions as x,y pairs (one per line)
+  --product-count N    generate N products along the shelf rows instead (default 20000)
+  --robots N           fleet size (default 30)
+  --seconds S          simulated seconds to run (default 60)
+  --tick-ms MS         tick length in milliseconds (default 50)
+  --seed N             random seed (default 1)
+  --out DIR            output directory (default sim-output)";
+
+struct Options {
+    config: StoreConfig,
+    products: Option<Vec<f32>>,
+    product_count: usize,
+    robots: usize,
+    seconds: f32,
+    tick_ms: f32,
+    seed: u32,
+    out: PathBuf,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+
+    match parse_options(&args).and_then(|options| run(&options)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("three-sim: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        config: StoreConfig::from_buffer(&DEFAULT_CONFIG),
+        products: None,
+        product_count: 20_000,
+        robots: 30,
+        seconds: 60.0,
+        tick_ms: 50.0,
+        seed: 1,
+        out: PathBuf::from("sim-output"),
+    };
+
+    let mut iter = args.iter();
+    while let Some(flag) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .ok_or_else(|| format!("missing value for {flag}\n\n{USAGE}"))
+        };
+        match flag.as_str() {
+            "--config" => {
+                let config = read_floats(value()?)?;
+                options.config = StoreConfig::parse(&config).map_err(|err| err.to_string())?;
+            }
+            "--products" => {
+                let products = read_floats(value()?)?;
+                if !products.len().is_multiple_of(2) {
+                    return Err("products file must contain x,y pairs".to_string());
+                }
+                options.products = Some(products);
+            }
+            "--product-count" => options.product_count = parse_number(flag, value()?)?,
+            "--robots" => options.robots = parse_number(flag, value()?)?,
+            "--seconds" => options.seconds = parse_number(flag, value()?)?,
+            "--tick-ms" => options.tick_ms = parse_number(flag, value()?)?,
+            "--seed" => options.seed = parse_number(flag, value()?)?,
+            "--out" => options.out = PathBuf::from(value()?),
+            other => return Err(format!("unknown argument {other}\n\n{USAGE}")),
+        }
+    }
+
+    if options.tick_ms <= 0.0 {
+        return Err("--tick-ms must be positive".to_string());
+    }
+    Ok(options)
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {flag}: {value}"))
+}
+
+/// Read every number in a file, ignoring `#` comments and any comma/whitespace layout
+fn read_floats(path: &str) -> Result<Vec<f32>, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("cannot read {path}: {err}"))?;
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(|line| line.split(|c: char| c == ',' || c.is_whitespace()))
+        .filter(|token| !token.is_empty())
+        .map(|token| {
+            token
+                .parse::<f32>()
+                .map_err(|_| format!("invalid number '{token}' in {path}"))
+        })
+        .collect()
+}
+
+/// Products along each shelf row, spread over the row length like the store map's mock data
+fn generate_products(count: usize, config: &StoreConfig, rng: &mut Rng) -> Vec<f32> {
+    let rows = config.row_count.max(1) as usize;
+    let mut products = Vec::with_capacity(count * 2);
+    for row in 0..rows {
+        let row_count = count / rows + usize::from(row < count % rows);
+        let span = config.store_height - 30.0;
+        let step = if row_count > 1 {
+            span / (row_count - 1) as f32
+        } else {
+            0.0
+        };
+        let row_x = config.get_row_center(row as i32);
+        for i in 0..row_count {
+            let x = row_x + (rng.next_f32() - 0.5) * config.row_thickness * 0.35;
+            let along = if row_count > 1 {
+                i as f32 * step
+            } else {
+                span / 2.0
+            };
+            let y = -config.store_height / 2.0 + 15.0 + along + (rng.next_f32() - 0.5) * 2.0;
+            let (wx, wy) = config.transform_coords(x, y);
+            products.extend_from_slice(&[wx, wy]);
+        }
+    }
+    products
+}
+
+/// Spawn robots in the cross-row walkways at the top or bottom edge, heading for the far end
+fn spawn_robots(sim: &mut Simulation, count: usize, config: &StoreConfig, rng: &mut Rng) {
+    let walkways = (config.row_count - 1).max(1) as f32;
+    let walkway_x = |rng: &mut Rng| {
+        let row = (rng.next_f32() * walkways).floor() as i32;
+        (config.get_row_center(row) + config.get_row_center(row + 1)) / 2.0
+    };
+    for i in 0..count {
+        let start_at_top = rng.next_f32() > 0.5;
+        let edge_y = config.store_height / 2.0 - 10.0;
+        let (start_y, dest_y) = if start_at_top {
+            (edge_y, -edge_y)
+        } else {
+            (-edge_y, edge_y)
+        };
+        let (x, y) = config.transform_coords(walkway_x(rng), start_y);
+        let (dest_x, dest_y) = config.transform_coords(walkway_x(rng), dest_y);
+        let orientation = rng.next_f32() * std::f32::consts::TAU;
+        let speed = MIN_ROBOT_SPEED + rng.next_f32() * (MAX_ROBOT_SPEED - MIN_ROBOT_SPEED);
+        // Cycle walking, tracked and dome bodies like the store map does
+        let variant = (i % 3) as u8;
+        sim.add_robot(x, y, dest_x, dest_y, orientation, speed, variant);
+    }
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let config = options.config;
+    let mut rng = Rng::new(options.seed as u64);
+    let mut sim = Simulation::with_config(config);
+    sim.set_seed(options.seed);
+
+    let products = match &options.products {
+        Some(products) => products.clone(),
+        None => generate_products(options.product_count, &config, &mut rng),
+    };
+    sim.set_products(&products);
+    spawn_robots(&mut sim, options.robots, &config, &mut rng);
+
+    fs::create_dir_all(&options.out)
+        .map_err(|err| format!("cannot create {}: {err}", options.out.display()))?;
+    let trajectories_path = options.out.join("trajectories.csv");
+    let events_path = options.out.join("events.csv");
+    let metrics_path = options.out.join("metrics.csv");
+    let heatmap_path = options.out.join("heatmap.csv");
+    let io_error = |path: &PathBuf| {
+        let path = path.display().to_string();
+        move |err: std::io::Error| format!("cannot write {path}: {err}")
+    };
+
+    let file = fs::File::create(&trajectories_path).map_err(io_error(&trajectories_path))?;
+    let mut trajectories = BufWriter::new(file);
+    writeln!(
+        trajectories,
+        "tick,time_ms,robot_id,x,y,orientation,dest_x,dest_y,battery"
+    )
+    .map_err(io_error(&trajectories_path))?;
+
+    let file = fs::File::create(&events_path).map_err(io_error(&events_path))?;
+    let mut events = BufWriter::new(file);
+    writeln!(events, "time_ms,robot_id,kind,x,y,value").map_err(io_error(&events_path))?;
+    let mut event_count = 0;
+
+    let ids = sim.robot_ids();
+    let ticks = (options.seconds * 1000.0 / options.tick_ms).ceil() as u64;
+
+    for tick in 0..=ticks {
+        if tick > 0 {
+            sim.step(options.tick_ms);
+        }
+        let states = sim.robot_states();
+        let time_ms = tick as f32 * options.tick_ms;
+        for (i, state) in states[HEADER_LEN..].chunks_exact(ROBOT_STRIDE).enumerate() {
+            writeln!(
+                trajectories,
+                "{tick},{time_ms},{},{},{},{},{},{},{}",
+                ids[i], state[0], state[1], state[4], state[2], state[3], state[7]
+            )
+            .map_err(io_error(&trajectories_path))?;
+        }
+
+        for event in sim.drain_events().chunks_exact(EVENT_STRIDE) {
+            writeln!(
+                events,
+                "{},{},{},{},{},{}",
+                event[0], event[1], event[2], event[3], event[4], event[5]
+            )
+            .map_err(io_error(&events_path))?;
+            event_count += 1;
+        }
+    }
+    trajectories.flush().map_err(io_error(&trajectories_path))?;
+    events.flush().map_err(io_error(&events_path))?;
+
+    let file = fs::File::create(&metrics_path).map_err(io_error(&metrics_path))?;
+    let mut metrics_out = BufWriter::new(file);
+    writeln!(
+        metrics_out,
+        "robot_id,distance,moving_ms,blocked_ms,idle_ms,collisions_avoided,tasks_completed,mean_cycle_ms"
+    )
+    .map_err(io_error(&metrics_path))?;
+    for id in &ids {
+        let Ok(robot) = sim.robot_stats(*id) else {
+            continue;
+        };
+        writeln!(
+            metrics_out,
+            "{id},{},{},{},{},{},{},{}",
+            robot.distance,
+            robot.moving_ms,
+            robot.blocked_ms,
+            robot.idle_ms,
+            robot.collisions_avoided,
+            robot.tasks_completed,
+            robot.mean_cycle_ms()
+        )
+        .map_err(io_error(&metrics_path))?;
+    }
+    metrics_out.flush().map_err(io_error(&metrics_path))?;
+
+    // Visited cells only, centred in world coordinates
+    let file = fs::File::create(&heatmap_path).map_err(io_error(&heatmap_path))?;
+    let mut heatmap = BufWriter::new(file);
+    writeln!(heatmap, "col,row,x,y,occupancy_ms,wait_ms").map_err(io_error(&heatmap_path))?;
+    let occupancy = sim.occupancy_heatmap();
+    let wait = sim.wait_heatmap();
+    // Header: [cols, rows, cellSize, minX, minY] in the world frame
+    let (cols, cell_size) = (occupancy[0] as usize, occupancy[2]);
+    let (min_x, min_y) = (occupancy[3], occupancy[4]);
+    let cells = occupancy[HEATMAP_HEADER_LEN..]
+        .iter()
+        .zip(&wait[HEATMAP_HEADER_LEN..]);
+    for (index, (occupancy_ms, wait_ms)) in cells.enumerate() {
+        if *occupancy_ms <= 0.0 {
+            continue;
+        }
+        let (col, row) = (index % cols, index / cols);
+        let x = min_x + (col as f32 + 0.5) * cell_size;
+        let y = min_y + (row as f32 + 0.5) * cell_size;
+        writeln!(heatmap, "{col},{row},{x},{y},{occupancy_ms},{wait_ms}")
+            .map_err(io_error(&heatmap_path))?;
+    }
+    heatmap.flush().map_err(io_error(&heatmap_path))?;
+
+    let fleet = sim.fleet_stats();
+    let robot_ms = fleet.moving_ms + fleet.blocked_ms + fleet.idle_ms;
+    println!(
+        "simulated {}s with {} robots and {} products (seed {})",
+        options.seconds,
+        ids.len(),
+        sim.product_count(),
+        options.seed
+    );
+    println!("total distance: {:.1}", fleet.distance);
+    if robot_ms > 0.0 {
+        println!(
+            "time moving / blocked / idle: {:.1}% / {:.1}% / {:.1}%",
+            fleet.moving_ms / robot_ms * 100.0,
+            fleet.blocked_ms / robot_ms * 100.0,
+            fleet.idle_ms / robot_ms * 100.0
+        );
+    }
+    println!("collisions avoided: {}", fleet.collisions_avoided);
+    println!(
+        "tasks completed: {} ({:.1} per hour, mean cycle {:.0} ms)",
+        fleet.tasks_completed, fleet.tasks_per_hour, fleet.mean_cycle_ms
+    );
+    println!("events: {event_count}");
+    println!(
+        "wrote {}, {}, {} and {}",
+        trajectories_path.display(),
+        events_path.display(),
+        metrics_path.display(),
+        heatmap_path.display()
+    );
+    Ok(())
+}
@@ -1,19 +1,28 @@
+use crate::error::{SimError, require_len};
+use crate::layout::{CONFIG_LAYOUT, CONFIG_LEN};
+
 pub const UPDATE_INTERVAL_DEFAULT: f32 = 50.0;
 pub const STUCK_TIMEOUT: f32 = 3000.0;
 pub const ROBOT_RADIUS: f32 = 2.0;
 pub const PRODUCT_RADIUS: f32 = 0.5;
 pub const COLLISION_BUFFER: f32 = 0.5;
+pub const PRODUCT_CLEARANCE: f32 = ROBOT_RADIUS + PRODUCT_RADIUS + COLLISION_BUFFER;
+/// Closest two robot centres may get before the separation pass pushes them apart
+pub const ROBOT_SEPARATION: f32 = ROBOT_RADIUS * 2.0;
+pub const SEPARATION_ITERATIONS: usize = 2;
 pub const NAV_CELL_SIZE: f32 = 5.0;
 pub const ARRIVAL_DISTANCE: f32 = 2.5;
 pub const ARRIVAL_DISTANCE_SQUARED: f32 = ARRIVAL_DISTANCE * ARRIVAL_DISTANCE;
+/// How far ahead along a path the follower steers towards when the caller gives no lookahead
+pub const PATH_LOOKAHEAD_DEFAULT: f32 = NAV_CELL_SIZE * 1.5;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Orientation {
     Vertical = 0,
     Horizontal = 1,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct StoreConfig {
     pub store_width: f32,
     pub store_height: f32,
@@ -28,9 +37,17 @@ pub struct StoreConfig {
 }
 
 impl StoreConfig {
+    /// Parse a versioned config buffer: [version, storeWidth, ..., orientation]
+    pub fn parse(buffer: &[f32]) -> Result<Self, SimError> {
+        let fields = CONFIG_LAYOUT.body(buffer)?;
+        require_len("config", fields, CONFIG_LEN)?;
+        Ok(StoreConfig::from_buffer(fields))
+    }
+
+    /// Read the config fields without a version header, defaulting any that are missing
     pub fn from_buffer(config: &[f32]) -> Self {
         StoreConfig {
-            store_width: config.get(0).copied().unwrap_or(250.0),
+            store_width: config.first().copied().unwrap_or(250.0),
             store_height: config.get(1).copied().unwrap_or(150.0),
             row_count: config.get(2).copied().unwrap_or(6.0) as i32,
             row_spacing: config.get(3).copied().unwrap_or(40.0),
@@ -47,10 +64,35 @@ impl StoreConfig {
         }
     }
 
+    /// Config fields in `from_buffer` order, without the version header
+    pub fn to_buffer(&self) -> [f32; CONFIG_LEN] {
+        [
+            self.store_width,
+            self.store_height,
+            self.row_count as f32,
+            self.row_spacing,
+            self.row_thickness,
+            self.start_offset,
+            self.walkway_width,
+            self.cross_row_buffer,
+            self.outer_walkway_offset,
+            match self.orientation {
+                Orientation::Horizontal => 1.0,
+                Orientation::Vertical => 0.0,
+            },
+        ]
+    }
+
     pub fn get_row_center(&self, row_index: i32) -> f32 {
         -self.store_width / 2.0 + self.start_offset + (row_index as f32) * self.row_spacing
     }
 
+    /// Index of the shelf row whose centre is closest to store-frame `x`, clamped to the rows
+    pub fn nearest_row(&self, x: f32) -> i32 {
+        let index = ((x + self.store_width / 2.0 - self.start_offset) / self.row_spacing).round();
+        (index as i32).clamp(0, (self.row_count - 1).max(0))
+    }
+
     pub fn transform_coords(&self, x: f32, y: f32) -> (f32, f32) {
         match self.orientation {
             Orientation::Horizontal => (y, x),
@@ -58,6 +100,19 @@ impl StoreConfig {
         }
     }
 
+    pub fn transform_products(&self, products: &[f32]) -> Vec<f32> {
+        match self.orientation {
+            Orientation::Horizontal => products
+                .chunks_exact(2)
+                .flat_map(|chunk| {
+                    let (x, y) = self.transform_coords(chunk[0], chunk[1]);
+                    [x, y]
+                })
+                .collect(),
+            Orientation::Vertical => products.to_vec(),
+        }
+    }
+
     pub fn transform_orientation(&self, angle: f32) -> f32 {
         match self.orientation {
             Orientation::Horizontal => std::f32::consts::FRAC_PI_2 - angle,
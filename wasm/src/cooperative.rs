@@ -0,0 +1,390 @@
+use crate::constants::StoreConfig;
+use crate::grid::NavGrid;
+use crate::pathfinding::*;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// Extra time steps a robot may spend waiting or detouring beyond twice its solo path length
+const HORIZON_SLACK: u32 = 32;
+
+/// One robot's route through space-time: `steps[t]` is the cell centre it occupies at step `t`.
+/// Consecutive equal entries are waits. `conflict_free` is false when no reservation-respecting
+/// route was found inside the horizon and the solo A* path was returned instead.
+pub struct TimedPath {
+    pub steps: Vec<(f32, f32)>,
+    pub conflict_free: bool,
+}
+
+/// Start and goal for one robot, in the store frame
+#[derive(Clone, Copy, Debug)]
+pub struct PathRequest {
+    pub start: (f32, f32),
+    pub goal: (f32, f32),
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+struct TimedNode {
+    idx: usize,
+    t: u32,
+    f_score: i32,
+}
+
+impl Ord for TimedNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Min-heap on f, preferring later time steps on ties so searches dive towards the goal
+        other
+            .f_score
+            .cmp(&self.f_score)
+            .then_with(|| self.t.cmp(&other.t))
+    }
+}
+
+impl PartialOrd for TimedNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Space-time reservations made by robots that have already been planned
+struct ReservationTable {
+    /// Grid width, to tell diagonal moves apart
+    cols: usize,
+    cells: HashSet<(usize, u32)>,
+    edges: HashSet<(usize, usize, u32)>,
+    /// 2x2 blocks crossed by a diagonal move, keyed by their top-left cell, so two robots
+    /// cannot cut across the same block on opposite diagonals
+    squares: HashSet<(usize, u32)>,
+    /// Cells where a robot parks for good, with the step it arrives
+    parked: HashMap<usize, u32>,
+    /// Latest step at which each cell is reserved, used to decide if a goal can be held
+    last_reserved: HashMap<usize, u32>,
+}
+
+impl ReservationTable {
+    fn new(cols: usize) -> Self {
+        ReservationTable {
+            cols,
+            cells: HashSet::new(),
+            edges: HashSet::new(),
+            squares: HashSet::new(),
+            parked: HashMap::new(),
+            last_reserved: HashMap::new(),
+        }
+    }
+
+    /// Top-left cell of the 2x2 block a diagonal move cuts across, None for other moves
+    fn crossed_square(&self, from: usize, to: usize) -> Option<usize> {
+        let (from_col, from_row) = (from % self.cols, from / self.cols);
+        let (to_col, to_row) = (to % self.cols, to / self.cols);
+        (from_col != to_col && from_row != to_row)
+            .then(|| from_row.min(to_row) * self.cols + from_col.min(to_col))
+    }
+
+    fn is_cell_free(&self, idx: usize, t: u32) -> bool {
+        !self.cells.contains(&(idx, t)) && self.parked.get(&idx).is_none_or(|since| t < *since)
+    }
+
+    /// True unless another robot crosses the same edge in the opposite direction, or cuts
+    /// across the same 2x2 block diagonally during the same step
+    fn is_edge_free(&self, from: usize, to: usize, t: u32) -> bool {
+        !self.edges.contains(&(to, from, t))
+            && self
+                .crossed_square(from, to)
+                .is_none_or(|square| !self.squares.contains(&(square, t)))
+    }
+
+    fn can_hold(&self, idx: usize, t: u32) -> bool {
+        self.last_reserved.get(&idx).is_none_or(|last| t > *last) && !self.parked.contains_key(&idx)
+    }
+
+    fn reserve(&mut self, cells: &[usize]) {
+        for (t, idx) in cells.iter().enumerate() {
+            let t = t as u32;
+            self.cells.insert((*idx, t));
+            let last = self.last_reserved.entry(*idx).or_insert(t);
+            *last = (*last).max(t);
+            if t > 0 {
+                let from = cells[t as usize - 1];
+                self.edges.insert((from, *idx, t - 1));
+                if let Some(square) = self.crossed_square(from, *idx) {
+                    self.squares.insert((square, t - 1));
+                }
+            }
+        }
+        if let Some(goal) = cells.last() {
+            self.parked.insert(*goal, cells.len() as u32 - 1);
+        }
+    }
+}
+
+/// Cooperative A*: robots are planned one after another in input order, each one searching
+/// (cell, time) space around the reservations left by the robots before it. Robots may wait in
+/// place, never share a cell at the same step, never swap cells head-on, never cut across the
+/// same 2x2 block on crossing diagonals, and once a robot reaches its goal it keeps the cell.
+pub fn plan_cooperative_paths(
+    requests: &[PathRequest],
+    grid: &NavGrid,
+    connectivity: Connectivity,
+    search: &mut PathSearch,
+) -> Vec<TimedPath> {
+    let mut reservations = ReservationTable::new(grid.cols().max(1) as usize);
+    let mut paths = Vec::with_capacity(requests.len());
+
+    for request in requests {
+        let solo = search.find_path(request.start, request.goal, grid, connectivity);
+        let horizon = solo.waypoints.len() as u32 * 2 + HORIZON_SLACK;
+
+        match search_space_time(
+            request.start,
+            request.goal,
+            grid,
+            connectivity,
+            &reservations,
+            horizon,
+        ) {
+            Some(cells) => {
+                reservations.reserve(&cells);
+                paths.push(TimedPath {
+                    steps: cells.iter().map(|idx| cell_center_of(grid, *idx)).collect(),
+                    conflict_free: true,
+                });
+            }
+            None => paths.push(TimedPath {
+                steps: solo.waypoints,
+                conflict_free: false,
+            }),
+        }
+    }
+
+    paths
+}
+
+/// Parse packed [startX, startY, goalX, goalY, ...] world-frame requests into store-frame pairs
+pub fn unpack_path_requests(requests: &[f32], config: &StoreConfig) -> Vec<PathRequest> {
+    requests
+        .chunks_exact(4)
+        .map(|chunk| PathRequest {
+            start: config.transform_coords(chunk[0], chunk[1]),
+            goal: config.transform_coords(chunk[2], chunk[3]),
+        })
+        .collect()
+}
+
+/// Output per robot: [conflictFree, stepCount, x0, y0, x1, y1, ...] in world coordinates,
+/// where the waypoint index is the time step
+pub fn pack_timed_paths(paths: &[TimedPath], config: &StoreConfig) -> Vec<f32> {
+    let mut output = Vec::new();
+    for path in paths {
+        output.push(if path.conflict_free { 1.0 } else { 0.0 });
+        output.push(path.steps.len() as f32);
+        for (x, y) in &path.steps {
+            let (tx, ty) = config.transform_coords(*x, *y);
+            output.extend_from_slice(&[tx, ty]);
+        }
+    }
+    output
+}
+
+fn cell_center_of(grid: &NavGrid, idx: usize) -> (f32, f32) {
+    let col = (idx % grid.cols() as usize) as i32;
+    let row = (idx / grid.cols() as usize) as i32;
+    grid.cell_center(col, row)
+}
+
+fn search_space_time(
+    start: (f32, f32),
+    goal: (f32, f32),
+    grid: &NavGrid,
+    connectivity: Connectivity,
+    reservations: &ReservationTable,
+    horizon: u32,
+) -> Option<Vec<usize>> {
+    if grid.is_empty() {
+        return None;
+    }
+    let (start_col, start_row) = grid.cell_at(start.0, start.1);
+    let (goal_col, goal_row) = grid.cell_at(goal.0, goal.1);
+    let (start_col, start_row) = find_nearest_walkable(start_col, start_row, grid);
+    let (goal_col, goal_row) = find_nearest_walkable(goal_col, goal_row, grid);
+    let start_idx = grid.index(start_col, start_row);
+    let goal_idx = grid.index(goal_col, goal_row);
+    if !reservations.is_cell_free(start_idx, 0) {
+        return None;
+    }
+
+    let mut open = BinaryHeap::new();
+    let mut g_score: HashMap<(usize, u32), i32> = HashMap::new();
+    let mut came_from: HashMap<(usize, u32), (usize, u32)> = HashMap::new();
+    let mut closed: HashSet<(usize, u32)> = HashSet::new();
+
+    g_score.insert((start_idx, 0), 0);
+    open.push(TimedNode {
+        idx: start_idx,
+        t: 0,
+        f_score: heuristic((start_col, start_row), (goal_col, goal_row), connectivity),
+    });
+
+    while let Some(current) = open.pop() {
+        let state = (current.idx, current.t);
+        if !closed.insert(state) {
+            continue;
+        }
+        if current.idx == goal_idx && reservations.can_hold(goal_idx, current.t) {
+            let mut cells = vec![current.idx];
+            let mut curr = state;
+            while let Some(prev) = came_from.get(&curr) {
+                cells.push(prev.0);
+                curr = *prev;
+            }
+            cells.reverse();
+            return Some(cells);
+        }
+        if current.t >= horizon {
+            continue;
+        }
+
+        let col = (current.idx % grid.cols() as usize) as i32;
+        let row = (current.idx / grid.cols() as usize) as i32;
+        let next_t = current.t + 1;
+        let current_g = g_score[&state];
+        let wait = std::iter::once((col, row, STRAIGHT_COST));
+        for (nc, nr, step_cost) in wait.chain(neighbors(col, row, grid, connectivity)) {
+            let next_idx = grid.index(nc, nr);
+            let next_state = (next_idx, next_t);
+            if closed.contains(&next_state)
+                || !reservations.is_cell_free(next_idx, next_t)
+                || !reservations.is_edge_free(current.idx, next_idx, current.t)
+            {
+                continue;
+            }
+            let tentative_g = current_g + step_cost;
+            if tentative_g < *g_score.get(&next_state).unwrap_or(&i32::MAX) {
+                g_score.insert(next_state, tentative_g);
+                came_from.insert(next_state, state);
+                open.push(TimedNode {
+                    idx: next_idx,
+                    t: next_t,
+                    f_score: tentative_g + heuristic((nc, nr), (goal_col, goal_row), connectivity),
+                });
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::build_nav_grid;
+
+    fn open_grid() -> NavGrid {
+        let mut grid = build_nav_grid(&StoreConfig::from_buffer(&[]));
+        for row in 0..grid.rows() {
+            for col in 0..grid.cols() {
+                grid.set_walkable(col, row, true);
+            }
+        }
+        grid
+    }
+
+    /// Start and goal cell of one robot
+    type CellRequest = ((i32, i32), (i32, i32));
+
+    fn plan(grid: &NavGrid, cells: &[CellRequest], connectivity: Connectivity) -> Vec<TimedPath> {
+        let requests: Vec<_> = cells
+            .iter()
+            .map(|(start, goal)| PathRequest {
+                start: grid.cell_center(start.0, start.1),
+                goal: grid.cell_center(goal.0, goal.1),
+            })
+            .collect();
+        plan_cooperative_paths(&requests, grid, connectivity, &mut PathSearch::new())
+    }
+
+    /// Cell of every robot at every step, robots staying on their goal once they arrive
+    fn cells_over_time(grid: &NavGrid, paths: &[TimedPath]) -> Vec<Vec<usize>> {
+        let steps = paths.iter().map(|path| path.steps.len()).max().unwrap_or(0);
+        paths
+            .iter()
+            .map(|path| {
+                (0..steps)
+                    .map(|t| {
+                        let (x, y) = path.steps[t.min(path.steps.len() - 1)];
+                        let (col, row) = grid.cell_at(x, y);
+                        grid.index(col, row)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn assert_no_conflicts(grid: &NavGrid, paths: &[TimedPath]) {
+        assert!(paths.iter().all(|path| path.conflict_free));
+        let table = ReservationTable::new(grid.cols() as usize);
+        let cells = cells_over_time(grid, paths);
+        for (i, a) in cells.iter().enumerate() {
+            for b in &cells[i + 1..] {
+                for t in 0..a.len() {
+                    assert_ne!(a[t], b[t], "robots share a cell at step {t}");
+                    if t + 1 == a.len() {
+                        continue;
+                    }
+                    assert!(
+                        !(a[t] == b[t + 1] && a[t + 1] == b[t]),
+                        "robots swap cells at step {t}"
+                    );
+                    let crossed = (
+                        table.crossed_square(a[t], a[t + 1]),
+                        table.crossed_square(b[t], b[t + 1]),
+                    );
+                    assert!(
+                        !matches!(crossed, (Some(x), Some(y)) if x == y),
+                        "robots cross diagonals at step {t}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn head_on_robots_pass_without_swapping() {
+        let grid = open_grid();
+        let paths = plan(
+            &grid,
+            &[((2, 5), (12, 5)), ((12, 5), (2, 5))],
+            Connectivity::Four,
+        );
+
+        assert_no_conflicts(&grid, &paths);
+    }
+
+    #[test]
+    fn crossing_robots_never_share_a_cell() {
+        let grid = open_grid();
+        let requests = [
+            ((2, 2), (12, 12)),
+            ((12, 2), (2, 12)),
+            ((7, 0), (7, 14)),
+            ((0, 7), (14, 7)),
+            ((12, 12), (2, 2)),
+        ];
+        for connectivity in [Connectivity::Four, Connectivity::Eight] {
+            let paths = plan(&grid, &requests, connectivity);
+            assert_no_conflicts(&grid, &paths);
+        }
+    }
+
+    #[test]
+    fn crossing_diagonals_through_one_block_are_rejected() {
+        let grid = open_grid();
+        let cell = |col, row| grid.index(col, row);
+        let mut table = ReservationTable::new(grid.cols() as usize);
+        table.reserve(&[cell(3, 3), cell(4, 4)]);
+
+        assert!(!table.is_edge_free(cell(4, 3), cell(3, 4), 0));
+        assert!(!table.is_edge_free(cell(3, 4), cell(4, 3), 0));
+        assert!(table.is_edge_free(cell(4, 3), cell(3, 4), 1));
+        assert!(table.is_edge_free(cell(5, 3), cell(4, 4), 0));
+    }
+}
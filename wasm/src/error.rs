@@ -0,0 +1,294 @@
+use std::fmt;
+
+/// Reasons an export rejects its input instead of producing a result
+#[derive(Clone, Debug, PartialEq)]
+pub enum SimError {
+    /// A fixed-layout buffer has fewer floats than its layout needs
+    BufferTooShort {
+        buffer: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    /// A packed buffer is not a whole number of records
+    BufferStride {
+        buffer: &'static str,
+        stride: usize,
+        actual: usize,
+    },
+    /// Two buffers that are combined element by element have different lengths
+    LengthMismatch {
+        left: &'static str,
+        right: &'static str,
+        left_len: usize,
+        right_len: usize,
+    },
+    /// A versioned buffer was built for a different layout version
+    LayoutVersion {
+        buffer: &'static str,
+        expected: u32,
+        actual: f32,
+    },
+    /// No robot with this id exists in the simulation
+    UnknownRobot(u32),
+    /// No product with this id exists (or it has been removed)
+    UnknownProduct(u32),
+    /// A snapshot blob could not be decoded
+    InvalidSnapshot(&'static str),
+    /// `finish_recording` was called without `start_recording`
+    NotRecording,
+    /// The robot is driving to, waiting for or sitting on a charger and cannot take a path
+    RobotCharging(u32),
+}
+
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimError::BufferTooShort {
+                buffer,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{buffer} buffer needs at least {expected} floats, got {actual}"
+            ),
+            SimError::BufferStride {
+                buffer,
+                stride,
+                actual,
+            } => write!(
+                f,
+                "{buffer} buffer length {actual} is not a multiple of {stride}"
+            ),
+            SimError::LengthMismatch {
+                left,
+                right,
+                left_len,
+                right_len,
+            } => write!(
+                f,
+                "{left} and {right} buffers differ in length ({left_len} vs {right_len})"
+            ),
+            SimError::LayoutVersion {
+                buffer,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{buffer} buffer has layout version {actual}, expected {expected}"
+            ),
+            SimError::UnknownRobot(id) => write!(f, "no robot with id {id}"),
+            SimError::UnknownProduct(id) => write!(f, "no product with id {id}"),
+            SimError::InvalidSnapshot(reason) => write!(f, "invalid snapshot: {reason}"),
+            SimError::NotRecording => write!(f, "the simulation is not recording"),
+            SimError::RobotCharging(id) => {
+                write!(f, "robot {id} is charging and cannot take a new path")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SimError {}
+
+impl SimError {
+    /// Stable camelCase identifier of the variant, e.g. `unknownRobot`, so callers can tell
+    /// errors apart without parsing the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            SimError::BufferTooShort { .. } => "bufferTooShort",
+            SimError::BufferStride { .. } => "bufferStride",
+            SimError::LengthMismatch { .. } => "lengthMismatch",
+            SimError::LayoutVersion { .. } => "layoutVersion",
+            SimError::UnknownRobot(_) => "unknownRobot",
+            SimError::UnknownProduct(_) => "unknownProduct",
+            SimError::InvalidSnapshot(_) => "invalidSnapshot",
+            SimError::NotRecording => "notRecording",
+            SimError::RobotCharging(_) => "robotCharging",
+        }
+    }
+}
+
+/// Error type of the exports: a thrown JS `Error` under the `wasm` feature, `SimError` otherwise
+#[cfg(feature = "wasm")]
+pub use js::ExportError;
+#[cfg(not(feature = "wasm"))]
+pub type ExportError = SimError;
+
+#[cfg(feature = "wasm")]
+mod js {
+    use super::SimError;
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen]
+    extern "C" {
+        #[wasm_bindgen(js_name = Error)]
+        type JsErrorObject;
+
+        #[wasm_bindgen(constructor, js_class = "Error")]
+        fn new(message: &str) -> JsErrorObject;
+
+        #[wasm_bindgen(method, setter)]
+        fn set_name(this: &JsErrorObject, name: &str);
+
+        #[wasm_bindgen(method, setter)]
+        fn set_code(this: &JsErrorObject, code: &str);
+    }
+
+    /// JS `Error` named `SimError` whose `code` is `SimError::code`, e.g.
+    /// `err.code === 'robotCharging'`. Only constructible inside a JS host.
+    #[derive(Debug)]
+    pub struct ExportError(JsValue);
+
+    impl From<SimError> for ExportError {
+        fn from(error: SimError) -> Self {
+            let object = JsErrorObject::new(&error.to_string());
+            object.set_name("SimError");
+            object.set_code(error.code());
+            ExportError(object.into())
+        }
+    }
+
+    impl From<ExportError> for JsValue {
+        fn from(error: ExportError) -> Self {
+            error.0
+        }
+    }
+}
+
+pub fn require_len(buffer: &'static str, data: &[f32], expected: usize) -> Result<(), SimError> {
+    if data.len() < expected {
+        return Err(SimError::BufferTooShort {
+            buffer,
+            expected,
+            actual: data.len(),
+        });
+    }
+    Ok(())
+}
+
+pub fn require_stride(buffer: &'static str, data: &[f32], stride: usize) -> Result<(), SimError> {
+    if !data.len().is_multiple_of(stride) {
+        return Err(SimError::BufferStride {
+            buffer,
+            stride,
+            actual: data.len(),
+        });
+    }
+    Ok(())
+}
+
+pub fn require_same_len(
+    left: &'static str,
+    left_data: &[f32],
+    right: &'static str,
+    right_data: &[f32],
+) -> Result<(), SimError> {
+    if left_data.len() != right_data.len() {
+        return Err(SimError::LengthMismatch {
+            left,
+            right,
+            left_len: left_data.len(),
+            right_len: right_data.len(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(all(test, not(feature = "wasm")))]
+mod tests {
+    use super::*;
+    use crate::layout::{CONFIG_LEN, LAYOUT_VERSION};
+    use crate::simulation::Simulation;
+    use crate::simulation::tests::busy_simulation;
+    use crate::{dot_products, magnitudes};
+
+    #[test]
+    fn exports_return_typed_errors() {
+        let mut sim = busy_simulation(3);
+        sim.step(50.0);
+        let short_config = [LAYOUT_VERSION as f32, 250.0];
+        let mut old_config = vec![LAYOUT_VERSION as f32 - 1.0];
+        old_config.extend([0.0; CONFIG_LEN]);
+
+        let errors = [
+            Simulation::new(&short_config).err(),
+            magnitudes(&[1.0, 2.0]).err(),
+            dot_products(&[1.0, 2.0, 3.0], &[1.0]).err(),
+            Simulation::new(&old_config).err(),
+            sim.set_robot_path(99, &[0.0, 0.0]).err(),
+            sim.pickup_pose_for_product(0, 99, 8).err(),
+            Simulation::restore(&[]).err(),
+            sim.finish_recording().err(),
+            // Robot 3 starts low and is on its way to the charger
+            sim.set_robot_path(3, &[0.0, 0.0]).err(),
+        ];
+        let expected = [
+            SimError::BufferTooShort {
+                buffer: "config",
+                expected: CONFIG_LEN,
+                actual: 1,
+            },
+            SimError::BufferStride {
+                buffer: "points",
+                stride: 3,
+                actual: 2,
+            },
+            SimError::LengthMismatch {
+                left: "a",
+                right: "b",
+                left_len: 3,
+                right_len: 1,
+            },
+            SimError::LayoutVersion {
+                buffer: "config",
+                expected: LAYOUT_VERSION,
+                actual: LAYOUT_VERSION as f32 - 1.0,
+            },
+            SimError::UnknownRobot(99),
+            SimError::UnknownProduct(99),
+            SimError::InvalidSnapshot("snapshot is truncated"),
+            SimError::NotRecording,
+            SimError::RobotCharging(3),
+        ];
+        for (error, expected) in errors.into_iter().zip(expected) {
+            assert_eq!(error, Some(expected));
+        }
+    }
+
+    #[test]
+    fn every_variant_has_its_own_code() {
+        let codes = [
+            SimError::BufferTooShort {
+                buffer: "",
+                expected: 0,
+                actual: 0,
+            },
+            SimError::BufferStride {
+                buffer: "",
+                stride: 0,
+                actual: 0,
+            },
+            SimError::LengthMismatch {
+                left: "",
+                right: "",
+                left_len: 0,
+                right_len: 0,
+            },
+            SimError::LayoutVersion {
+                buffer: "",
+                expected: 0,
+                actual: 0.0,
+            },
+            SimError::UnknownRobot(0),
+            SimError::UnknownProduct(0),
+            SimError::InvalidSnapshot(""),
+            SimError::NotRecording,
+            SimError::RobotCharging(0),
+        ]
+        .map(|error| error.code());
+
+        for (i, code) in codes.iter().enumerate() {
+            assert!(!codes[..i].contains(code), "{code} is used twice");
+            assert!(code.starts_with(|c: char| c.is_ascii_lowercase()));
+        }
+    }
+}
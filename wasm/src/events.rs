@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+
+use crate::constants::StoreConfig;
+use crate::error::SimError;
+use crate::snapshot::{SnapshotReader, SnapshotWriter};
+
+/// Floats per event in `Simulation::drain_events` output
+pub const EVENT_STRIDE: usize = 6;
+
+/// Undrained events kept before the oldest are dropped, about a minute of a busy fleet
+pub const MAX_PENDING_EVENTS: usize = 65_536;
+
+/// Things worth explaining after a run, numbered as they appear in event buffers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    /// The simulation picked a new destination; (x, y) is the destination
+    DestinationChanged = 0,
+    /// The robot bumped into a product at (x, y) and bounced off
+    ProductCollision = 1,
+    /// The robot had not moved for `STUCK_TIMEOUT`; (x, y) is where it was stuck
+    StuckTimeout = 2,
+    /// The robot reached its destination or a waypoint of its path at (x, y)
+    WaypointReached = 3,
+    /// The robot's battery task changed; value is 0 working, 1 driving to a charger,
+    /// 2 waiting for a charger, 3 charging
+    PhaseChanged = 4,
+    /// The robot was given a path with `set_robot_path`; (x, y) is its end and value its
+    /// length
+    PathAssigned = 5,
+    /// The robot reached the end of its assigned path; value is the cycle time in ms
+    TaskCompleted = 6,
+    /// The robot gave up its assigned path before the end, because it was cleared or
+    /// replaced, the robot left for a charger or was removed; value is the time spent on it
+    TaskAbandoned = 7,
+}
+
+impl EventKind {
+    fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(EventKind::DestinationChanged),
+            1 => Some(EventKind::ProductCollision),
+            2 => Some(EventKind::StuckTimeout),
+            3 => Some(EventKind::WaypointReached),
+            4 => Some(EventKind::PhaseChanged),
+            5 => Some(EventKind::PathAssigned),
+            6 => Some(EventKind::TaskCompleted),
+            7 => Some(EventKind::TaskAbandoned),
+            _ => None,
+        }
+    }
+}
+
+/// One entry of the event log; positions are in the store frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SimEvent {
+    /// Simulated time at the end of the tick the event happened in
+    pub time_ms: f64,
+    pub robot: u32,
+    pub kind: EventKind,
+    pub x: f32,
+    pub y: f32,
+    pub value: f32,
+}
+
+impl SimEvent {
+    /// Encoded size: time, robot, kind, position, value
+    pub const SNAPSHOT_SIZE: usize = 8 + 4 + 1 + 8 + 4;
+
+    /// Output: [timeMs, robotId, kind, x, y, value] with (x, y) in world coordinates
+    pub fn to_world(&self, config: &StoreConfig) -> [f32; EVENT_STRIDE] {
+        let (x, y) = config.transform_coords(self.x, self.y);
+        [
+            self.time_ms as f32,
+            self.robot as f32,
+            self.kind as u8 as f32,
+            x,
+            y,
+            self.value,
+        ]
+    }
+
+    pub fn write(&self, out: &mut SnapshotWriter) {
+        out.f64(self.time_ms);
+        out.u32(self.robot);
+        out.u8(self.kind as u8);
+        out.point((self.x, self.y));
+        out.f32(self.value);
+    }
+
+    pub fn read(input: &mut SnapshotReader) -> Result<Self, SimError> {
+        Ok(SimEvent {
+            time_ms: input.f64()?,
+            robot: input.u32()?,
+            kind: EventKind::from_code(input.u8()?)
+                .ok_or(SimError::InvalidSnapshot("invalid event kind"))?,
+            x: input.f32()?,
+            y: input.f32()?,
+            value: input.f32()?,
+        })
+    }
+}
+
+/// Events waiting for `drain_events`. Bounded so a host that never drains does not grow it
+/// forever: once full, the oldest events are dropped and counted.
+#[derive(Clone, Debug, Default)]
+pub struct EventQueue {
+    events: VecDeque<SimEvent>,
+    dropped: usize,
+}
+
+impl EventQueue {
+    pub fn push(&mut self, event: SimEvent) {
+        if self.events.len() >= MAX_PENDING_EVENTS {
+            self.events.pop_front();
+            self.dropped += 1;
+        }
+        self.events.push_back(event);
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Events dropped unread since the simulation started
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = SimEvent> + '_ {
+        self.events.drain(..)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(robot: u32) -> SimEvent {
+        SimEvent {
+            time_ms: robot as f64,
+            robot,
+            kind: EventKind::WaypointReached,
+            x: 0.0,
+            y: 0.0,
+            value: 0.0,
+        }
+    }
+
+    #[test]
+    fn a_full_queue_drops_and_counts_its_oldest_events() {
+        let mut queue = EventQueue::default();
+        let overflow = 10;
+        for robot in 0..(MAX_PENDING_EVENTS + overflow) as u32 {
+            queue.push(event(robot));
+        }
+
+        assert_eq!(queue.len(), MAX_PENDING_EVENTS);
+        assert_eq!(queue.dropped(), overflow);
+        let kept: Vec<u32> = queue.drain().map(|event| event.robot).collect();
+        assert_eq!(kept.first(), Some(&(overflow as u32)));
+        assert_eq!(
+            kept.last(),
+            Some(&((MAX_PENDING_EVENTS + overflow - 1) as u32))
+        );
+        assert!(kept.windows(2).all(|pair| pair[1] == pair[0] + 1));
+
+        // Draining frees the space; the count covers the whole run
+        assert!(queue.is_empty());
+        queue.push(event(0));
+        assert_eq!((queue.len(), queue.dropped()), (1, overflow));
+    }
+}
@@ -0,0 +1,269 @@
+use crate::constants::*;
+use crate::geometry::shortest_angle_diff;
+use crate::kinematics::KinematicLimits;
+
+/// Polyline with cumulative arc lengths, for projecting onto and sampling along a path
+#[derive(Clone, Debug)]
+pub struct Polyline {
+    points: Vec<(f32, f32)>,
+    cumulative: Vec<f32>,
+}
+
+impl Polyline {
+    pub fn new(points: Vec<(f32, f32)>) -> Self {
+        let mut cumulative = Vec::with_capacity(points.len());
+        let mut total = 0.0;
+        for (i, point) in points.iter().enumerate() {
+            if i > 0 {
+                let prev = points[i - 1];
+                total += (point.0 - prev.0).hypot(point.1 - prev.1);
+            }
+            cumulative.push(total);
+        }
+        Polyline { points, cumulative }
+    }
+
+    pub fn points(&self) -> &[(f32, f32)] {
+        &self.points
+    }
+
+    /// Distance along the path at which each point is reached
+    pub fn point_distances(&self) -> &[f32] {
+        &self.cumulative
+    }
+
+    pub fn length(&self) -> f32 {
+        self.cumulative.last().copied().unwrap_or(0.0)
+    }
+
+    /// Point at arc length `s`, clamped to the ends
+    pub fn point_at(&self, s: f32) -> (f32, f32) {
+        if self.points.is_empty() {
+            return (0.0, 0.0);
+        }
+        let s = s.clamp(0.0, self.length());
+        let segment = self.cumulative.partition_point(|d| *d <= s).max(1);
+        if segment >= self.points.len() {
+            return self.points[self.points.len() - 1];
+        }
+        let (a, b) = (self.points[segment - 1], self.points[segment]);
+        let span = self.cumulative[segment] - self.cumulative[segment - 1];
+        let t = if span > 0.0001 {
+            (s - self.cumulative[segment - 1]) / span
+        } else {
+            0.0
+        };
+        (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
+    }
+
+    /// Arc length of the closest point on the path, ignoring everything before `from`
+    /// so progress never jumps backwards on paths that pass close to themselves
+    pub fn project(&self, p: (f32, f32), from: f32) -> f32 {
+        if self.points.len() < 2 {
+            return 0.0;
+        }
+        let mut best_s = from.clamp(0.0, self.length());
+        let start = self.point_at(best_s);
+        let mut best_dist_sq = (p.0 - start.0).powi(2) + (p.1 - start.1).powi(2);
+        for i in 1..self.points.len() {
+            if self.cumulative[i] < from {
+                continue;
+            }
+            let (a, b) = (self.points[i - 1], self.points[i]);
+            let (seg_dx, seg_dy) = (b.0 - a.0, b.1 - a.1);
+            let seg_len_sq = seg_dx * seg_dx + seg_dy * seg_dy;
+            let t = if seg_len_sq > 0.0001 {
+                (((p.0 - a.0) * seg_dx + (p.1 - a.1) * seg_dy) / seg_len_sq).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let s = (self.cumulative[i - 1] + t * seg_len_sq.sqrt()).max(from);
+            let q = self.point_at(s);
+            let dist_sq = (p.0 - q.0).powi(2) + (p.1 - q.1).powi(2);
+            if dist_sq < best_dist_sq {
+                best_dist_sq = dist_sq;
+                best_s = s;
+            }
+        }
+        best_s
+    }
+}
+
+/// Pose and progress of a robot following a polyline.
+/// Headings use the `update_robots` convention: 0 faces +y and forward is (sin h, cos h).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FollowState {
+    pub x: f32,
+    pub y: f32,
+    pub heading: f32,
+    pub velocity: f32,
+    /// Arc length of the robot's projection onto the path
+    pub progress: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FollowResult {
+    pub state: FollowState,
+    pub remaining: f32,
+    pub done: bool,
+}
+
+/// One pure-pursuit tick: steer along the arc through a lookahead point `lookahead` units further
+/// down the path, with speed and turn rate bounded by the drive limits. The robot only slows
+/// for the end of the path (and to pivot on sharp turns), not for intermediate waypoints.
+pub fn pure_pursuit_step(
+    path: &Polyline,
+    state: FollowState,
+    cruise_speed: f32,
+    lookahead: f32,
+    limits: &KinematicLimits,
+    delta_ms: f32,
+) -> FollowResult {
+    let dt = delta_ms / 1000.0;
+    let progress = path.project((state.x, state.y), state.progress);
+    let goal = path.point_at(path.length());
+    let goal_distance = (goal.0 - state.x).hypot(goal.1 - state.y);
+    let remaining = (path.length() - progress).max(goal_distance);
+
+    if path.points().is_empty() || remaining <= ARRIVAL_DISTANCE || dt <= 0.0 {
+        return FollowResult {
+            state: FollowState {
+                velocity: 0.0,
+                progress,
+                ..state
+            },
+            remaining,
+            done: remaining <= ARRIVAL_DISTANCE,
+        };
+    }
+
+    let target = path.point_at(progress + lookahead.max(0.1));
+    let (to_x, to_y) = (target.0 - state.x, target.1 - state.y);
+    let target_distance = to_x.hypot(to_y).max(0.0001);
+    let alpha = shortest_angle_diff(to_x.atan2(to_y), state.heading);
+
+    let pivoting = limits
+        .turn_in_place_threshold
+        .is_some_and(|threshold| alpha.abs() > threshold);
+    let braking_speed = (2.0 * limits.max_acceleration * remaining).sqrt();
+    let target_velocity = if pivoting {
+        0.0
+    } else {
+        cruise_speed.min(braking_speed)
+    };
+    let max_dv = limits.max_acceleration * dt;
+    let velocity =
+        (state.velocity + (target_velocity - state.velocity).clamp(-max_dv, max_dv)).max(0.0);
+
+    // Pure pursuit curvature through the lookahead point; pivot directly when stopped
+    let max_omega = limits.max_angular_velocity;
+    let omega = if pivoting || velocity < 0.0001 {
+        (alpha / dt).clamp(-max_omega, max_omega)
+    } else {
+        let curvature = 2.0 * alpha.sin() / target_distance;
+        (velocity * curvature).clamp(-max_omega, max_omega)
+    };
+
+    let mid_heading = state.heading + omega * dt / 2.0;
+    let travel = (velocity * dt).min(remaining);
+    let x = state.x + mid_heading.sin() * travel;
+    let y = state.y + mid_heading.cos() * travel;
+    let progress = path.project((x, y), progress);
+    let remaining = (path.length() - progress).max((goal.0 - x).hypot(goal.1 - y));
+
+    FollowResult {
+        state: FollowState {
+            x,
+            y,
+            heading: state.heading + omega * dt,
+            velocity,
+            progress,
+        },
+        remaining,
+        done: remaining <= ARRIVAL_DISTANCE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kinematics::RobotVariant;
+
+    fn l_shaped_path() -> Polyline {
+        Polyline::new(vec![(0.0, 0.0), (0.0, 10.0), (10.0, 10.0)])
+    }
+
+    /// Follow `path` from `state` until done, returning the final state and the furthest `y`
+    fn follow(path: &Polyline, mut state: FollowState, lookahead: f32) -> (FollowState, f32) {
+        let limits = RobotVariant::Dome.limits();
+        let mut max_y = state.y;
+        for _ in 0..2000 {
+            let result = pure_pursuit_step(path, state, 4.0, lookahead, &limits, 50.0);
+            state = result.state;
+            max_y = max_y.max(state.y);
+            if result.done {
+                return (state, max_y);
+            }
+        }
+        panic!("never reached the end of the path");
+    }
+
+    #[test]
+    fn points_project_onto_the_closest_segment() {
+        let path = l_shaped_path();
+        assert_eq!(path.length(), 20.0);
+        assert_eq!(path.project((3.0, 5.0), 0.0), 5.0);
+        assert_eq!(path.project((5.0, 12.0), 0.0), 15.0);
+        // Off either end, projection clamps to the ends
+        assert_eq!(path.project((0.0, -5.0), 0.0), 0.0);
+        assert_eq!(path.project((25.0, 10.0), 0.0), 20.0);
+        // Progress never moves back before `from`
+        assert_eq!(path.project((0.0, 2.0), 12.0), 12.0);
+        assert_eq!(path.point_at(15.0), (5.0, 10.0));
+    }
+
+    #[test]
+    fn lookahead_past_the_end_steers_onto_the_goal() {
+        let path = Polyline::new(vec![(0.0, 0.0), (0.0, 10.0)]);
+        assert_eq!(path.point_at(path.length() + 5.0), (0.0, 10.0));
+
+        let start = FollowState {
+            x: 1.0,
+            y: 4.0,
+            ..FollowState::default()
+        };
+        let (end, max_y) = follow(&path, start, 20.0);
+        let remaining = end.x.hypot(end.y - 10.0);
+        assert!(remaining <= ARRIVAL_DISTANCE);
+        assert!(max_y <= 10.0, "drove past the goal to y = {max_y}");
+
+        // Once in range it stays put
+        let limits = RobotVariant::Dome.limits();
+        let result = pure_pursuit_step(&path, end, 4.0, 20.0, &limits, 50.0);
+        assert!(result.done);
+        assert_eq!((result.state.x, result.state.y), (end.x, end.y));
+        assert_eq!(result.state.velocity, 0.0);
+    }
+
+    #[test]
+    fn single_point_paths_drive_straight_to_the_point() {
+        let path = Polyline::new(vec![(3.0, 4.0)]);
+        assert_eq!(path.length(), 0.0);
+        assert_eq!(path.project((10.0, 10.0), 0.0), 0.0);
+        assert_eq!(path.point_at(5.0), (3.0, 4.0));
+
+        let (end, _) = follow(&path, FollowState::default(), 5.0);
+        assert!((end.x - 3.0).hypot(end.y - 4.0) <= ARRIVAL_DISTANCE);
+
+        // Already there: done without moving
+        let at_goal = FollowState {
+            x: 3.0,
+            y: 4.0,
+            ..FollowState::default()
+        };
+        let limits = RobotVariant::Dome.limits();
+        let result = pure_pursuit_step(&path, at_goal, 4.0, 5.0, &limits, 50.0);
+        assert!(result.done);
+        assert_eq!((result.state.x, result.state.y), (3.0, 4.0));
+    }
+}
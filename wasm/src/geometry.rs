@@ -1,9 +1,3 @@
-use js_sys::Math;
-
-pub fn random_f32() -> f32 {
-    Math::random() as f32
-}
-
 pub fn clamp(value: f32, min: f32, max: f32) -> f32 {
     value.max(min).min(max)
 }
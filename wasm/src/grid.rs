@@ -2,13 +2,20 @@ use crate::constants::*;
 use crate::geometry::clamp;
 
 pub fn world_to_col(x: f32, width: f32) -> i32 {
-    let col = ((x + width / 2.0) / NAV_CELL_SIZE).floor() as i32;
-    col
+    ((x + width / 2.0) / NAV_CELL_SIZE).floor() as i32
 }
 
 pub fn world_to_row(y: f32, height: f32) -> i32 {
-    let row = ((y + height / 2.0) / NAV_CELL_SIZE).floor() as i32;
-    row
+    ((y + height / 2.0) / NAV_CELL_SIZE).floor() as i32
+}
+
+/// Largest nav grid side in cells; bigger stores are clamped so a bad config cannot demand an
+/// unbounded grid
+pub const MAX_NAV_GRID_SIZE: i32 = 4096;
+
+/// Cells needed to cover `extent`, clamped to `0..=MAX_NAV_GRID_SIZE` (NaN covers none)
+fn cells_across(extent: f32) -> i32 {
+    ((extent / NAV_CELL_SIZE).ceil() as i32).clamp(0, MAX_NAV_GRID_SIZE)
 }
 
 pub fn cell_center_x(col: i32, width: f32) -> f32 {
@@ -19,10 +26,199 @@ pub fn cell_center_y(row: i32, height: f32) -> f32 {
     row as f32 * NAV_CELL_SIZE - height / 2.0 + NAV_CELL_SIZE / 2.0
 }
 
-pub fn build_nav_grid(config: &StoreConfig) -> Vec<Vec<bool>> {
-    let cols = (config.store_width / NAV_CELL_SIZE).ceil() as i32;
-    let rows = (config.store_height / NAV_CELL_SIZE).ceil() as i32;
-    let mut grid = vec![vec![true; cols as usize]; rows as usize];
+/// Walkability grid at `NAV_CELL_SIZE` resolution, built once per `StoreConfig`.
+/// Cells are stored row-major; `true` means a robot may occupy the cell.
+#[derive(Clone, Debug)]
+pub struct NavGrid {
+    config: StoreConfig,
+    cols: i32,
+    rows: i32,
+    cells: Vec<bool>,
+}
+
+impl NavGrid {
+    pub fn config(&self) -> &StoreConfig {
+        &self.config
+    }
+
+    /// True if this grid was built from exactly this layout
+    pub fn is_built_for(&self, config: &StoreConfig) -> bool {
+        self.config == *config
+    }
+
+    pub fn cols(&self) -> i32 {
+        self.cols
+    }
+
+    pub fn rows(&self) -> i32 {
+        self.rows
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn in_bounds(&self, col: i32, row: i32) -> bool {
+        col >= 0 && row >= 0 && col < self.cols && row < self.rows
+    }
+
+    pub fn index(&self, col: i32, row: i32) -> usize {
+        row as usize * self.cols as usize + col as usize
+    }
+
+    pub fn is_walkable(&self, col: i32, row: i32) -> bool {
+        self.in_bounds(col, row) && self.cells[self.index(col, row)]
+    }
+
+    pub fn set_walkable(&mut self, col: i32, row: i32, walkable: bool) {
+        if self.in_bounds(col, row) {
+            let idx = self.index(col, row);
+            self.cells[idx] = walkable;
+        }
+    }
+
+    /// Cell containing a store-frame point, clamped onto the grid
+    pub fn cell_at(&self, x: f32, y: f32) -> (i32, i32) {
+        let col = world_to_col(x, self.config.store_width).clamp(0, (self.cols - 1).max(0));
+        let row = world_to_row(y, self.config.store_height).clamp(0, (self.rows - 1).max(0));
+        (col, row)
+    }
+
+    pub fn cell_center(&self, col: i32, row: i32) -> (f32, f32) {
+        (
+            cell_center_x(col, self.config.store_width),
+            cell_center_y(row, self.config.store_height),
+        )
+    }
+
+    /// Block every cell that comes within `PRODUCT_CLEARANCE` of a product.
+    /// Products format: [x1, y1, x2, y2, ...] in the store frame
+    pub fn block_products(&mut self, products: &[f32]) {
+        let half_cell = NAV_CELL_SIZE / 2.0;
+        let clearance_sq = PRODUCT_CLEARANCE * PRODUCT_CLEARANCE;
+        for chunk in products.chunks_exact(2) {
+            let (px, py) = (chunk[0], chunk[1]);
+            let (min_col, min_row) = self.cell_at(px - PRODUCT_CLEARANCE, py - PRODUCT_CLEARANCE);
+            let (max_col, max_row) = self.cell_at(px + PRODUCT_CLEARANCE, py + PRODUCT_CLEARANCE);
+            for row in min_row..=max_row {
+                for col in min_col..=max_col {
+                    let (cx, cy) = self.cell_center(col, row);
+                    let nearest_x = px.clamp(cx - half_cell, cx + half_cell);
+                    let nearest_y = py.clamp(cy - half_cell, cy + half_cell);
+                    let dx = px - nearest_x;
+                    let dy = py - nearest_y;
+                    if dx * dx + dy * dy < clearance_sq {
+                        self.set_walkable(col, row, false);
+                    }
+                }
+            }
+        }
+    }
+
+    /// True if every cell the segment between two store-frame points passes through is walkable.
+    /// Walks the grid with Amanatides–Woo traversal; when the segment crosses exactly through a
+    /// cell corner both side cells must be walkable.
+    pub fn has_line_of_sight(&self, from: (f32, f32), to: (f32, f32)) -> bool {
+        let x0 = (from.0 + self.config.store_width / 2.0) / NAV_CELL_SIZE;
+        let y0 = (from.1 + self.config.store_height / 2.0) / NAV_CELL_SIZE;
+        let x1 = (to.0 + self.config.store_width / 2.0) / NAV_CELL_SIZE;
+        let y1 = (to.1 + self.config.store_height / 2.0) / NAV_CELL_SIZE;
+
+        let mut col = x0.floor() as i32;
+        let mut row = y0.floor() as i32;
+        let end_col = x1.floor() as i32;
+        let end_row = y1.floor() as i32;
+        if !self.is_walkable(col, row) {
+            return false;
+        }
+
+        let dx = x1 - x0;
+        let dy = y1 - y0;
+        let step_col = if dx > 0.0 { 1 } else { -1 };
+        let step_row = if dy > 0.0 { 1 } else { -1 };
+        // Division by zero yields infinity, which keeps an axis-aligned ray on its axis
+        let t_delta_x = (1.0 / dx).abs();
+        let t_delta_y = (1.0 / dy).abs();
+        let mut t_max_x = if dx > 0.0 {
+            (col as f32 + 1.0 - x0) / dx
+        } else if dx < 0.0 {
+            (col as f32 - x0) / dx
+        } else {
+            f32::INFINITY
+        };
+        let mut t_max_y = if dy > 0.0 {
+            (row as f32 + 1.0 - y0) / dy
+        } else if dy < 0.0 {
+            (row as f32 - y0) / dy
+        } else {
+            f32::INFINITY
+        };
+
+        let max_steps = (end_col - col).abs() + (end_row - row).abs();
+        for _ in 0..max_steps {
+            if col == end_col && row == end_row {
+                break;
+            }
+            if t_max_x.is_finite() && (t_max_x - t_max_y).abs() < 1e-5 {
+                if !self.is_walkable(col + step_col, row) || !self.is_walkable(col, row + step_row)
+                {
+                    return false;
+                }
+                col += step_col;
+                row += step_row;
+                t_max_x += t_delta_x;
+                t_max_y += t_delta_y;
+            } else if t_max_x < t_max_y {
+                col += step_col;
+                t_max_x += t_delta_x;
+            } else {
+                row += step_row;
+                t_max_y += t_delta_y;
+            }
+            if !self.is_walkable(col, row) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Line of sight for a body of `radius` sweeping along the segment: the centreline and
+    /// both edges of the swept corridor must stay on walkable cells, so a shortcut cannot
+    /// clip a shelf corner that only the centreline clears.
+    pub fn has_clear_corridor(&self, from: (f32, f32), to: (f32, f32), radius: f32) -> bool {
+        if !self.has_line_of_sight(from, to) {
+            return false;
+        }
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let length = dx.hypot(dy);
+        if length < 1e-6 || radius <= 0.0 {
+            return true;
+        }
+        let (nx, ny) = (-dy / length * radius, dx / length * radius);
+        [1.0, -1.0].into_iter().all(|side: f32| {
+            let (ox, oy) = (nx * side, ny * side);
+            self.has_line_of_sight((from.0 + ox, from.1 + oy), (to.0 + ox, to.1 + oy))
+        })
+    }
+}
+
+pub fn build_nav_grid(config: &StoreConfig) -> NavGrid {
+    let cols = cells_across(config.store_width);
+    let rows = cells_across(config.store_height);
+    let cell_count = (cols as usize)
+        .checked_mul(rows as usize)
+        .expect("nav grid dimensions are capped");
+    let mut grid = NavGrid {
+        config: *config,
+        cols,
+        rows,
+        cells: vec![true; cell_count],
+    };
 
     let half_shelf = config.row_thickness / 2.0 + 1.5;
     for row in 0..config.row_count {
@@ -41,7 +237,7 @@ pub fn build_nav_grid(config: &StoreConfig) -> Vec<Vec<bool>> {
         ) as i32;
         for col in min_col..=max_col {
             for row in 0..rows {
-                grid[row as usize][col as usize] = false;
+                grid.set_walkable(col, row, false);
             }
         }
     }
@@ -77,3 +273,59 @@ pub fn is_in_row_walkway(x: f32, y: f32, config: &StoreConfig) -> bool {
 
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Distance from a point to the nearest point of a cell
+    fn distance_to_cell(grid: &NavGrid, (x, y): (f32, f32), col: i32, row: i32) -> f32 {
+        let (cx, cy) = grid.cell_center(col, row);
+        let half_cell = NAV_CELL_SIZE / 2.0;
+        let dx = x - x.clamp(cx - half_cell, cx + half_cell);
+        let dy = y - y.clamp(cy - half_cell, cy + half_cell);
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    #[test]
+    fn shelves_block_their_columns() {
+        let config = StoreConfig::from_buffer(&[]);
+        let grid = build_nav_grid(&config);
+        let (shelf_col, _) = grid.cell_at(config.get_row_center(2), 0.0);
+        let (aisle_col, _) = grid.cell_at(-45.0, 0.0);
+
+        assert!((0..grid.rows()).all(|row| !grid.is_walkable(shelf_col, row)));
+        assert!((0..grid.rows()).all(|row| grid.is_walkable(aisle_col, row)));
+    }
+
+    #[test]
+    fn huge_stores_are_capped() {
+        let mut config = StoreConfig::from_buffer(&[]);
+        config.store_width = 1.0e9;
+        config.store_height = f32::MAX;
+        let grid = build_nav_grid(&config);
+
+        assert_eq!((grid.cols(), grid.rows()), (MAX_NAV_GRID_SIZE, MAX_NAV_GRID_SIZE));
+        assert_eq!(grid.len(), (MAX_NAV_GRID_SIZE * MAX_NAV_GRID_SIZE) as usize);
+    }
+
+    #[test]
+    fn products_block_cells_within_clearance() {
+        let config = StoreConfig::from_buffer(&[]);
+        let before = build_nav_grid(&config);
+        let mut grid = before.clone();
+        let product = (-44.0, 11.0);
+        grid.block_products(&[product.0, product.1]);
+
+        let mut blocked = 0;
+        for row in 0..grid.rows() {
+            for col in 0..grid.cols() {
+                let near = distance_to_cell(&grid, product, col, row) < PRODUCT_CLEARANCE;
+                let expected = before.is_walkable(col, row) && !near;
+                assert_eq!(grid.is_walkable(col, row), expected, "cell ({col}, {row})");
+                blocked += usize::from(before.is_walkable(col, row) && near);
+            }
+        }
+        assert!(blocked > 1);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::constants::{NAV_CELL_SIZE, Orientation, StoreConfig};
+use crate::error::SimError;
+use crate::grid::NavGrid;
+use crate::snapshot::{SnapshotReader, SnapshotWriter};
+
+/// Floats before the cells in `occupancy_buffer` / `wait_buffer`:
+/// [cols, rows, cellSize, minX, minY]
+pub const HEATMAP_HEADER_LEN: usize = 5;
+
+/// Robot time spent in each nav grid cell, and how much of it was spent waiting.
+/// Cells are kept like the nav grid (store frame, row-major, `NAV_CELL_SIZE` on a side)
+/// and turned into the world frame on output.
+#[derive(Clone, Debug)]
+pub struct TrafficHeatmap {
+    cols: i32,
+    rows: i32,
+    occupancy_ms: Vec<f64>,
+    wait_ms: Vec<f64>,
+}
+
+impl TrafficHeatmap {
+    /// Empty heatmap covering the same cells as `grid`
+    pub fn new(grid: &NavGrid) -> Self {
+        TrafficHeatmap {
+            cols: grid.cols(),
+            rows: grid.rows(),
+            occupancy_ms: vec![0.0; grid.len()],
+            wait_ms: vec![0.0; grid.len()],
+        }
+    }
+
+    /// Count a tick spent at a store-frame position; `waiting` when the robot was blocked
+    pub fn record(&mut self, grid: &NavGrid, (x, y): (f32, f32), waiting: bool, delta_ms: f32) {
+        if grid.is_empty() {
+            return;
+        }
+        let (col, row) = grid.cell_at(x, y);
+        let index = grid.index(col, row);
+        self.occupancy_ms[index] += delta_ms as f64;
+        if waiting {
+            self.wait_ms[index] += delta_ms as f64;
+        }
+    }
+
+    /// Output: header, then robot-ms per cell, row-major in the world frame
+    pub fn occupancy_buffer(&self, config: &StoreConfig) -> Vec<f32> {
+        self.to_world_buffer(&self.occupancy_ms, config)
+    }
+
+    /// Output: header, then robot-ms spent blocked per cell, row-major in the world frame
+    pub fn wait_buffer(&self, config: &StoreConfig) -> Vec<f32> {
+        self.to_world_buffer(&self.wait_ms, config)
+    }
+
+    /// Header [cols, rows, cellSize, minX, minY] with cols along world x, so cell
+    /// `(col, row)` is centred at `minX + (col + 0.5) * cellSize`,
+    /// `minY + (row + 0.5) * cellSize`. Horizontal layouts swap x and y, which transposes
+    /// the store-frame grid.
+    fn to_world_buffer(&self, values: &[f64], config: &StoreConfig) -> Vec<f32> {
+        let (min_x, min_y) =
+            config.transform_coords(-config.store_width / 2.0, -config.store_height / 2.0);
+        let transposed = config.orientation == Orientation::Horizontal;
+        let (cols, rows) = if transposed {
+            (self.rows, self.cols)
+        } else {
+            (self.cols, self.rows)
+        };
+        let mut output = Vec::with_capacity(HEATMAP_HEADER_LEN + values.len());
+        output.extend_from_slice(&[cols as f32, rows as f32, NAV_CELL_SIZE, min_x, min_y]);
+        if transposed {
+            // World row r is store column r, world column c is store row c
+            for col in 0..self.cols {
+                for row in 0..self.rows {
+                    output.push(values[(row * self.cols + col) as usize] as f32);
+                }
+            }
+        } else {
+            output.extend(values.iter().map(|value| *value as f32));
+        }
+        output
+    }
+
+    /// Grid size, then only the visited cells, since most of the store never sees a robot
+    pub fn write(&self, out: &mut SnapshotWriter) {
+        out.u32(self.cols as u32);
+        out.u32(self.rows as u32);
+        let visited = || {
+            self.occupancy_ms
+                .iter()
+                .zip(&self.wait_ms)
+                .enumerate()
+                .filter(|(_, (occupancy, _))| **occupancy > 0.0)
+        };
+        out.count(visited().count());
+        for (index, (occupancy, wait)) in visited() {
+            out.count(index);
+            out.f64(*occupancy);
+            out.f64(*wait);
+        }
+    }
+
+    /// Read a heatmap written for `grid`, which must have the same size
+    pub fn read(input: &mut SnapshotReader, grid: &NavGrid) -> Result<Self, SimError> {
+        let (cols, rows) = (input.u32()?, input.u32()?);
+        if (cols as i32, rows as i32) != (grid.cols(), grid.rows()) {
+            return Err(SimError::InvalidSnapshot(
+                "heatmap does not match the nav grid",
+            ));
+        }
+        let mut heatmap = TrafficHeatmap::new(grid);
+        let visited = input.count(20)?;
+        for _ in 0..visited {
+            let index = input.u32()? as usize;
+            if index >= grid.len() {
+                return Err(SimError::InvalidSnapshot("heatmap cell out of range"));
+            }
+            heatmap.occupancy_ms[index] = input.f64()?;
+            heatmap.wait_ms[index] = input.f64()?;
+        }
+        Ok(heatmap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::build_nav_grid;
+
+    /// Value of the world-frame cell holding `(x, y)` in a heatmap buffer
+    fn world_cell(buffer: &[f32], (x, y): (f32, f32)) -> f32 {
+        let cols = buffer[0] as usize;
+        let (cell_size, min_x, min_y) = (buffer[2], buffer[3], buffer[4]);
+        let col = ((x - min_x) / cell_size).floor() as usize;
+        let row = ((y - min_y) / cell_size).floor() as usize;
+        buffer[HEATMAP_HEADER_LEN + row * cols + col]
+    }
+
+    #[test]
+    fn cells_are_indexed_in_the_world_frame_for_both_orientations() {
+        for orientation in [Orientation::Vertical, Orientation::Horizontal] {
+            let mut config = StoreConfig::from_buffer(&[]);
+            config.orientation = orientation;
+            let grid = build_nav_grid(&config);
+            let mut heatmap = TrafficHeatmap::new(&grid);
+            let (moving, waiting) = ((-43.0, 32.0), (12.0, -61.0));
+            heatmap.record(
+                &grid,
+                config.transform_coords(moving.0, moving.1),
+                false,
+                50.0,
+            );
+            heatmap.record(
+                &grid,
+                config.transform_coords(waiting.0, waiting.1),
+                true,
+                20.0,
+            );
+            heatmap.record(
+                &grid,
+                config.transform_coords(waiting.0, waiting.1),
+                true,
+                20.0,
+            );
+
+            let occupancy = heatmap.occupancy_buffer(&config);
+            let wait = heatmap.wait_buffer(&config);
+            let (cols, rows) = (occupancy[0], occupancy[1]);
+            let (world_width, world_height) = match orientation {
+                Orientation::Vertical => (config.store_width, config.store_height),
+                Orientation::Horizontal => (config.store_height, config.store_width),
+            };
+            assert_eq!(cols * NAV_CELL_SIZE, world_width, "{orientation:?}");
+            assert_eq!(rows * NAV_CELL_SIZE, world_height, "{orientation:?}");
+            assert_eq!(occupancy[3..5], [-world_width / 2.0, -world_height / 2.0]);
+            assert_eq!(wait[..HEATMAP_HEADER_LEN], occupancy[..HEATMAP_HEADER_LEN]);
+
+            assert_eq!(world_cell(&occupancy, moving), 50.0, "{orientation:?}");
+            assert_eq!(world_cell(&occupancy, waiting), 40.0, "{orientation:?}");
+            assert_eq!(world_cell(&wait, moving), 0.0, "{orientation:?}");
+            assert_eq!(world_cell(&wait, waiting), 40.0, "{orientation:?}");
+            let total: f32 = occupancy[HEATMAP_HEADER_LEN..].iter().sum();
+            assert_eq!(total, 90.0);
+        }
+    }
+}
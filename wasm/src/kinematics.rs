@@ -0,0 +1,206 @@
+use crate::geometry::shortest_angle_diff;
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+/// Robot body types, numbered like the store map's `Robot['variant']` list
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RobotVariant {
+    #[default]
+    Walking = 0,
+    Tracked = 1,
+    Dome = 2,
+}
+
+impl RobotVariant {
+    pub fn from_index(index: u8) -> Self {
+        match index {
+            1 => RobotVariant::Tracked,
+            2 => RobotVariant::Dome,
+            _ => RobotVariant::Walking,
+        }
+    }
+
+    pub fn limits(self) -> KinematicLimits {
+        match self {
+            // Legs turn while stepping, but take a while to get up to speed
+            RobotVariant::Walking => KinematicLimits {
+                max_acceleration: 2.0,
+                max_angular_velocity: 2.5,
+                turn_in_place_threshold: None,
+            },
+            // Skid-steer: slow to turn, pivots on the spot for sharp corners
+            RobotVariant::Tracked => KinematicLimits {
+                max_acceleration: 3.0,
+                max_angular_velocity: 1.5,
+                turn_in_place_threshold: Some(FRAC_PI_4),
+            },
+            // Light wheeled base with quick acceleration and turning
+            RobotVariant::Dome => KinematicLimits {
+                max_acceleration: 5.0,
+                max_angular_velocity: 4.0,
+                turn_in_place_threshold: Some(FRAC_PI_2),
+            },
+        }
+    }
+}
+
+/// Differential-drive limits; speeds are in store units per second
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KinematicLimits {
+    /// Linear acceleration and braking limit (units/s²)
+    pub max_acceleration: f32,
+    /// Turn rate limit (rad/s)
+    pub max_angular_velocity: f32,
+    /// Heading error above which the robot stops and pivots instead of driving on
+    pub turn_in_place_threshold: Option<f32>,
+}
+
+/// Velocity state carried between ticks for a kinematically driven robot
+#[derive(Clone, Copy, Debug)]
+pub struct Drive {
+    pub limits: KinematicLimits,
+    pub velocity: f32,
+}
+
+impl Drive {
+    pub fn new(variant: RobotVariant) -> Self {
+        Drive {
+            limits: variant.limits(),
+            velocity: 0.0,
+        }
+    }
+}
+
+/// Result of one integration step: the new heading and velocity, and how far to travel
+/// along the new heading this tick
+#[derive(Clone, Copy, Debug)]
+pub struct MotionStep {
+    pub heading: f32,
+    pub velocity: f32,
+    pub travel: f32,
+}
+
+/// Integrate one tick of differential-drive motion towards a goal.
+/// The heading turns towards `desired_heading` at most `max_angular_velocity * dt`; linear
+/// velocity ramps towards `cruise_speed` (or zero while pivoting) at most `max_acceleration * dt`
+/// and is capped so the robot can still brake to a stop at `distance`.
+/// Headings only need to share one convention with the caller; no axis is assumed here.
+pub fn integrate_motion(
+    heading: f32,
+    velocity: f32,
+    desired_heading: f32,
+    distance: f32,
+    cruise_speed: f32,
+    limits: &KinematicLimits,
+    delta_ms: f32,
+) -> MotionStep {
+    let dt = delta_ms / 1000.0;
+    let max_turn = limits.max_angular_velocity * dt;
+    let turn = shortest_angle_diff(desired_heading, heading).clamp(-max_turn, max_turn);
+    let heading = heading + turn;
+    let remaining_error = shortest_angle_diff(desired_heading, heading).abs();
+
+    let pivoting = limits
+        .turn_in_place_threshold
+        .is_some_and(|threshold| remaining_error > threshold);
+    let braking_speed = (2.0 * limits.max_acceleration * distance).sqrt();
+    let target_velocity = if pivoting {
+        0.0
+    } else {
+        cruise_speed.min(braking_speed)
+    };
+
+    let max_dv = limits.max_acceleration * dt;
+    let velocity = velocity + (target_velocity - velocity).clamp(-max_dv, max_dv);
+    let travel = (velocity * dt).clamp(0.0, distance);
+
+    MotionStep {
+        heading,
+        velocity,
+        travel,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELTA_MS: f32 = 50.0;
+
+    #[test]
+    fn velocity_ramps_at_the_acceleration_limit_up_to_cruise_speed() {
+        let limits = RobotVariant::Walking.limits();
+        let max_dv = limits.max_acceleration * DELTA_MS / 1000.0;
+        let mut velocity = 0.0;
+        for _ in 0..100 {
+            let step = integrate_motion(0.0, velocity, 0.0, 1000.0, 4.0, &limits, DELTA_MS);
+            assert!(step.velocity - velocity <= max_dv + 1e-6);
+            assert!(step.velocity <= 4.0);
+            velocity = step.velocity;
+        }
+        assert_eq!(velocity, 4.0);
+
+        // Braking is limited the same way
+        let step = integrate_motion(0.0, velocity, 0.0, 1000.0, 1.0, &limits, DELTA_MS);
+        assert!((velocity - step.velocity - max_dv).abs() < 1e-6);
+    }
+
+    #[test]
+    fn heading_turns_at_most_the_turn_rate() {
+        let limits = RobotVariant::Walking.limits();
+        let max_turn = limits.max_angular_velocity * DELTA_MS / 1000.0;
+        let mut heading = 0.0;
+        for _ in 0..30 {
+            let step = integrate_motion(heading, 1.0, 2.0, 1000.0, 1.0, &limits, DELTA_MS);
+            assert!(step.heading - heading <= max_turn + 1e-6);
+            heading = step.heading;
+        }
+        assert!((heading - 2.0).abs() < 1e-6);
+
+        // The short way round: from just left of pi to just right of -pi
+        let step = integrate_motion(3.0, 0.0, -3.0, 10.0, 1.0, &limits, DELTA_MS);
+        assert!(step.heading > 3.0);
+    }
+
+    #[test]
+    fn skid_steer_robots_stop_to_pivot_on_sharp_turns() {
+        let tracked = RobotVariant::Tracked.limits();
+        let step = integrate_motion(0.0, 2.0, 2.0, 1000.0, 4.0, &tracked, DELTA_MS);
+        assert!(step.velocity < 2.0);
+
+        // Walking robots keep driving through the same turn
+        let walking = RobotVariant::Walking.limits();
+        let step = integrate_motion(0.0, 2.0, 2.0, 1000.0, 4.0, &walking, DELTA_MS);
+        assert!(step.velocity > 2.0);
+    }
+
+    #[test]
+    fn robots_brake_to_arrive_without_overshooting() {
+        for variant in [
+            RobotVariant::Walking,
+            RobotVariant::Tracked,
+            RobotVariant::Dome,
+        ] {
+            let limits = variant.limits();
+            let (mut distance, mut velocity, mut top_speed) = (20.0, 0.0, 0.0_f32);
+            let mut ticks = 0;
+            while distance > 0.0 {
+                let step = integrate_motion(0.0, velocity, 0.0, distance, 6.0, &limits, DELTA_MS);
+                assert!(step.travel <= distance, "{variant:?} overshot");
+                // Once braking starts it never speeds up again
+                if velocity < top_speed {
+                    assert!(step.velocity <= velocity, "{variant:?}");
+                }
+                top_speed = top_speed.max(step.velocity);
+                distance -= step.travel;
+                velocity = step.velocity;
+                ticks += 1;
+                assert!(ticks < 1000, "{variant:?} never arrived");
+            }
+            assert_eq!(top_speed, 6.0, "{variant:?}");
+            assert!(
+                velocity < top_speed / 4.0,
+                "{variant:?} arrived at {velocity}"
+            );
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use crate::error::SimError;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// Version written as the first float of every robot and config buffer.
+/// Bump it whenever a field is added, removed or reordered.
+pub const LAYOUT_VERSION: u32 = 2;
+
+/// Number of floats before the first record of a versioned buffer
+pub const HEADER_LEN: usize = 1;
+
+/// Field order of a versioned buffer, so JS can build and read buffers without hard-coding offsets
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug)]
+pub struct BufferLayout {
+    name: &'static str,
+    fields: &'static [&'static str],
+}
+
+/// Packed robots: [version, then per robot: x, y, destX, destY, orientation, speed, lastMoveTime,
+/// battery]
+pub const ROBOT_LAYOUT: BufferLayout = BufferLayout {
+    name: "robots",
+    fields: &[
+        "x",
+        "y",
+        "destX",
+        "destY",
+        "orientation",
+        "speed",
+        "lastMoveTime",
+        "battery",
+    ],
+};
+
+/// Store config: [version, storeWidth, storeHeight, rowCount, rowSpacing, rowThickness,
+/// startOffset, walkwayWidth, crossRowBuffer, outerWalkwayOffset, orientation]
+pub const CONFIG_LAYOUT: BufferLayout = BufferLayout {
+    name: "config",
+    fields: &[
+        "storeWidth",
+        "storeHeight",
+        "rowCount",
+        "rowSpacing",
+        "rowThickness",
+        "startOffset",
+        "walkwayWidth",
+        "crossRowBuffer",
+        "outerWalkwayOffset",
+        "orientation",
+    ],
+};
+
+/// Floats per robot in robot buffers
+pub const ROBOT_STRIDE: usize = ROBOT_LAYOUT.fields.len();
+
+/// Floats in a config buffer after the header
+pub const CONFIG_LEN: usize = CONFIG_LAYOUT.fields.len();
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl BufferLayout {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn name(&self) -> String {
+        self.name.to_string()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn version(&self) -> u32 {
+        LAYOUT_VERSION
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter, js_name = headerLength))]
+    pub fn header_len(&self) -> usize {
+        HEADER_LEN
+    }
+
+    /// Floats per record, not counting the header
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn stride(&self) -> usize {
+        self.fields.len()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn fields(&self) -> Vec<String> {
+        self.fields.iter().map(|field| field.to_string()).collect()
+    }
+
+    /// Offset of a field within one record, or undefined if the layout has no such field
+    pub fn offset(&self, field: &str) -> Option<usize> {
+        self.fields.iter().position(|name| *name == field)
+    }
+}
+
+impl BufferLayout {
+    /// Check the version header and return the records that follow it
+    pub fn body<'a>(&self, buffer: &'a [f32]) -> Result<&'a [f32], SimError> {
+        let Some(&version) = buffer.first() else {
+            return Err(SimError::BufferTooShort {
+                buffer: self.name,
+                expected: HEADER_LEN,
+                actual: 0,
+            });
+        };
+        if version != LAYOUT_VERSION as f32 {
+            return Err(SimError::LayoutVersion {
+                buffer: self.name,
+                expected: LAYOUT_VERSION,
+                actual: version,
+            });
+        }
+        Ok(&buffer[HEADER_LEN..])
+    }
+
+    /// Empty output buffer with the version header already written
+    pub fn with_header(&self, records: usize) -> Vec<f32> {
+        let mut buffer = Vec::with_capacity(HEADER_LEN + records * self.fields.len());
+        buffer.push(LAYOUT_VERSION as f32);
+        buffer
+    }
+}
+
+/// Field layout of robot buffers (`update_robots`, `Simulation::robot_states`)
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn robot_buffer_layout() -> BufferLayout {
+    ROBOT_LAYOUT
+}
+
+/// Field layout of config buffers
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn config_buffer_layout() -> BufferLayout {
+    CONFIG_LAYOUT
+}
+
+#[cfg(all(test, not(feature = "wasm")))]
+mod tests {
+    use super::*;
+    use crate::constants::StoreConfig;
+    use crate::update_robots;
+
+    fn config_buffer() -> Vec<f32> {
+        let mut buffer = CONFIG_LAYOUT.with_header(1);
+        buffer.extend(StoreConfig::from_buffer(&[]).to_buffer());
+        buffer
+    }
+
+    #[test]
+    fn bodies_follow_the_version_header() {
+        let mut robots = ROBOT_LAYOUT.with_header(1);
+        assert_eq!(robots, [LAYOUT_VERSION as f32]);
+        robots.extend([1.0; ROBOT_STRIDE]);
+        assert_eq!(ROBOT_LAYOUT.body(&robots), Ok(&[1.0; ROBOT_STRIDE][..]));
+        assert_eq!(ROBOT_LAYOUT.offset("battery"), Some(ROBOT_STRIDE - 1));
+        assert_eq!(ROBOT_LAYOUT.offset("charge"), None);
+    }
+
+    #[test]
+    fn buffers_from_another_layout_version_are_rejected() {
+        let mut config = config_buffer();
+        config[0] = (LAYOUT_VERSION - 1) as f32;
+        assert_eq!(
+            StoreConfig::parse(&config),
+            Err(SimError::LayoutVersion {
+                buffer: "config",
+                expected: LAYOUT_VERSION,
+                actual: (LAYOUT_VERSION - 1) as f32,
+            })
+        );
+        // An unversioned buffer reads its first field as the version
+        assert!(matches!(
+            StoreConfig::parse(&config[1..]),
+            Err(SimError::LayoutVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn short_buffers_are_rejected() {
+        assert_eq!(
+            ROBOT_LAYOUT.body(&[]),
+            Err(SimError::BufferTooShort {
+                buffer: "robots",
+                expected: HEADER_LEN,
+                actual: 0,
+            })
+        );
+        let config = config_buffer();
+        assert_eq!(
+            StoreConfig::parse(&config[..CONFIG_LEN]),
+            Err(SimError::BufferTooShort {
+                buffer: "config",
+                expected: CONFIG_LEN,
+                actual: CONFIG_LEN - 1,
+            })
+        );
+    }
+
+    #[test]
+    fn robot_buffers_must_be_whole_records() {
+        let mut robots = ROBOT_LAYOUT.with_header(2);
+        robots.extend([0.0; ROBOT_STRIDE * 2 - 1]);
+        assert_eq!(
+            update_robots(&robots, &[], &config_buffer(), 50.0),
+            Err(SimError::BufferStride {
+                buffer: "robots",
+                stride: ROBOT_STRIDE,
+                actual: ROBOT_STRIDE * 2 - 1,
+            })
+        );
+        robots.push(0.0);
+        assert!(update_robots(&robots, &[], &config_buffer(), 50.0).is_ok());
+    }
+}
@@ -1,104 +1,268 @@
-mod constants;
-mod geometry;
-mod grid;
-mod pathfinding;
-mod robot;
+pub mod allocation;
+pub mod battery;
+pub mod constants;
+pub mod cooperative;
+pub mod error;
+pub mod events;
+pub mod follower;
+pub mod geometry;
+pub mod grid;
+pub mod heatmap;
+pub mod kinematics;
+pub mod layout;
+pub mod metrics;
+pub mod pathfinding;
+pub mod picking;
+pub mod replay;
+pub mod rng;
+pub mod robot;
+pub mod simulation;
+pub mod snapshot;
+pub mod spatial;
+pub mod validation;
 
+use allocation::*;
+use battery::BatteryModel;
 use constants::*;
+use cooperative::*;
+use error::*;
+use follower::{FollowState, Polyline, pure_pursuit_step};
+use grid::{NavGrid, build_nav_grid};
+use kinematics::{Drive, RobotVariant};
+use layout::{ROBOT_LAYOUT, ROBOT_STRIDE};
 use pathfinding::*;
+use picking::{pack_pick_route, pickup_pose};
+use rng::Rng;
 use robot::*;
+pub use simulation::Simulation;
+use spatial::ProductIndex;
+use std::cell::{Cell, RefCell};
+use validation::ConfigProblem;
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
+thread_local! {
+    /// Version of the product buffer declared with `set_products_version`
+    static PRODUCTS_VERSION: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+/// Tag the product buffer the next calls pass. `compute_path`, `update_robots` and the other
+/// exports taking products reuse their nav grid and spatial index while the version, the
+/// buffer length and a hash of its contents all match, so two callers that share a version
+/// number with different products still rebuild. Bump it whenever the products change.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn set_products_version(version: u32) {
+    PRODUCTS_VERSION.with(|current| current.set(Some(version)));
+}
+
+/// What a cache built from a product buffer was built for: the declared version plus the
+/// buffer's length and content hash, so no copy of the buffer is kept
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct ProductsKey {
+    version: Option<u32>,
+    len: usize,
+    hash: u64,
+}
+
+impl ProductsKey {
+    fn of(products: &[f32]) -> Self {
+        // FNV-1a over the float bits
+        let hash = products.iter().fold(0xcbf2_9ce4_8422_2325, |hash, value| {
+            (hash ^ value.to_bits() as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+        ProductsKey {
+            version: PRODUCTS_VERSION.with(Cell::get),
+            len: products.len(),
+            hash,
+        }
+    }
+}
+
+struct NavCache {
+    products: ProductsKey,
+    grid: NavGrid,
+    search: PathSearch,
+}
+
+thread_local! {
+    /// Nav grid and A* scratch space for the stateless exports, rebuilt only when the layout
+    /// or product set changes
+    static NAV_CACHE: RefCell<Option<NavCache>> = const { RefCell::new(None) };
+}
+
+fn with_nav_grid<R>(
+    config: &StoreConfig,
+    products: &[f32],
+    f: impl FnOnce(&NavGrid, &mut PathSearch) -> R,
+) -> R {
+    NAV_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let key = ProductsKey::of(products);
+        let stale = cache
+            .as_ref()
+            .is_none_or(|cached| !cached.grid.is_built_for(config) || cached.products != key);
+        if stale {
+            let mut grid = build_nav_grid(config);
+            grid.block_products(&config.transform_products(products));
+            *cache = Some(NavCache {
+                products: key,
+                grid,
+                search: PathSearch::new(),
+            });
+        }
+        let cached = cache.as_mut().expect("nav cache populated above");
+        f(&cached.grid, &mut cached.search)
+    })
+}
+
+struct ProductCache {
+    config: StoreConfig,
+    products: ProductsKey,
+    index: ProductIndex,
+}
+
+thread_local! {
+    /// Product spatial index for the stateless exports, rebuilt only when the layout or
+    /// product set changes
+    static PRODUCT_CACHE: RefCell<Option<ProductCache>> = const { RefCell::new(None) };
+}
+
+fn with_product_index<R>(
+    config: &StoreConfig,
+    products: &[f32],
+    f: impl FnOnce(&ProductIndex) -> R,
+) -> R {
+    PRODUCT_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let key = ProductsKey::of(products);
+        let stale = cache
+            .as_ref()
+            .is_none_or(|cached| cached.config != *config || cached.products != key);
+        if stale {
+            *cache = Some(ProductCache {
+                config: *config,
+                products: key,
+                index: ProductIndex::build(&config.transform_products(products), config),
+            });
+        }
+        let cached = cache.as_ref().expect("product cache populated above");
+        f(&cached.index)
+    })
+}
+
+thread_local! {
+    /// Random source for the stateless robot exports; reseed with `set_seed`
+    static SHARED_RNG: RefCell<Rng> = RefCell::new(Rng::default());
+}
+
+/// Reseed the random source used by `update_robots`, so identical inputs replay identically
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn set_seed(seed: u32) {
+    SHARED_RNG.with(|rng| *rng.borrow_mut() = Rng::new(seed as u64));
+}
+
 /// Compute vector magnitudes for packed XYZ positions (x1, y1, z1, x2, y2, z2, ...)
 /// This is a simple demo of the Rust→Wasm bridge for heavier math.
-#[wasm_bindgen]
-pub fn magnitudes(points: &[f32]) -> Vec<f32> {
-    if points.is_empty() || !points.len().is_multiple_of(3) {
-        return Vec::new();
-    }
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn magnitudes(points: &[f32]) -> Result<Vec<f32>, ExportError> {
+    require_stride("points", points, 3)?;
 
-    points
+    Ok(points
         .chunks(3)
         .map(|c| {
             let (x, y, z) = (c[0], c[1], c[2]);
             (x * x + y * y + z * z).sqrt()
         })
-        .collect()
+        .collect())
 }
 
 /// Compute per-vector dot products for two packed XYZ buffers of equal length.
-#[wasm_bindgen]
-pub fn dot_products(a: &[f32], b: &[f32]) -> Vec<f32> {
-    if a.len() != b.len() || a.is_empty() || !a.len().is_multiple_of(3) {
-        return Vec::new();
-    }
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn dot_products(a: &[f32], b: &[f32]) -> Result<Vec<f32>, ExportError> {
+    require_same_len("a", a, "b", b)?;
+    require_stride("a", a, 3)?;
 
-    a.chunks(3)
+    Ok(a.chunks(3)
         .zip(b.chunks(3))
         .map(|(a_vec, b_vec)| a_vec[0] * b_vec[0] + a_vec[1] * b_vec[1] + a_vec[2] * b_vec[2])
-        .collect()
+        .collect())
 }
 
 /// Linearly interpolate two packed XYZ buffers: result = a * (1 - t) + b * t.
-#[wasm_bindgen]
-pub fn lerp_vectors(a: &[f32], b: &[f32], t: f32) -> Vec<f32> {
-    if a.len() != b.len() || a.is_empty() || !a.len().is_multiple_of(3) {
-        return Vec::new();
-    }
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn lerp_vectors(a: &[f32], b: &[f32], t: f32) -> Result<Vec<f32>, ExportError> {
+    require_same_len("a", a, "b", b)?;
+    require_stride("a", a, 3)?;
 
     let clamped_t = t.clamp(0.0, 1.0);
-    a.iter()
+    Ok(a.iter()
         .zip(b.iter())
         .map(|(av, bv)| av * (1.0 - clamped_t) + bv * clamped_t)
-        .collect()
+        .collect())
 }
 
-/// Update multiple robots in parallel
-/// Input format per robot: [x, y, destX, destY, orientation, speed, lastMoveTime]
-/// Config format: [storeWidth, storeHeight, rowCount, rowSpacing, rowThickness, startOffset, walkwayWidth, crossRowBuffer, outerWalkwayOffset, orientation]
-#[wasm_bindgen]
-pub fn update_robots(robots: &[f32], products: &[f32], config: &[f32], delta_ms: f32) -> Vec<f32> {
-    if !robots.len().is_multiple_of(7) {
-        return Vec::new();
-    }
+/// Update multiple robots in parallel, then push apart any robots that ended up overlapping
+/// Batteries drain with distance driven and time stood still; a robot at 0 stays where it is.
+/// Charging needs charger state between ticks, so it is only modelled by `Simulation`.
+/// Input format: [version, then per robot: x, y, destX, destY, orientation, speed, lastMoveTime, battery]
+/// Config format: [version, storeWidth, storeHeight, rowCount, rowSpacing, rowThickness, startOffset, walkwayWidth, crossRowBuffer, outerWalkwayOffset, orientation]
+/// Output: same layout as the input; see `robot_buffer_layout` and `config_buffer_layout`
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn update_robots(
+    robots: &[f32],
+    products: &[f32],
+    config: &[f32],
+    delta_ms: f32,
+) -> Result<Vec<f32>, ExportError> {
+    let robots = ROBOT_LAYOUT.body(robots)?;
+    require_stride("robots", robots, ROBOT_STRIDE)?;
+    require_stride("products", products, 2)?;
 
-    let store_config = StoreConfig::from_buffer(config);
+    let store_config = StoreConfig::parse(config)?;
     let delta = if delta_ms > 0.0 {
         delta_ms
     } else {
         UPDATE_INTERVAL_DEFAULT
     };
-    let mut output: Vec<f32> = Vec::with_capacity(robots.len());
-    let transformed_products = if matches!(store_config.orientation, Orientation::Horizontal) {
-        let mut out = Vec::with_capacity(products.len());
-        for chunk in products.chunks_exact(2) {
-            let (x, y) = store_config.transform_coords(chunk[0], chunk[1]);
-            out.push(x);
-            out.push(y);
-        }
-        Some(out)
-    } else {
-        None
-    };
-    let products_ref = transformed_products
-        .as_deref()
-        .unwrap_or(products);
-
-    for chunk in robots.chunks_exact(7) {
-        let (x, y) = store_config.transform_coords(chunk[0], chunk[1]);
-        let (dest_x, dest_y) = store_config.transform_coords(chunk[2], chunk[3]);
-        let orientation = store_config.transform_orientation(chunk[4]);
-        let result = update_single_robot(
-            x,
-            y,
-            dest_x,
-            dest_y,
-            orientation,
-            chunk[5],
-            chunk[6],
-            products_ref,
-            &store_config, delta,
-        );
+    let mut results: Vec<_> = with_product_index(&store_config, products, |index| {
+        let mut rng = SHARED_RNG.with(|rng| rng.borrow().clone());
+        let results = robots
+            .chunks_exact(ROBOT_STRIDE)
+            .map(|chunk| {
+                let (x, y) = store_config.transform_coords(chunk[0], chunk[1]);
+                let (dest_x, dest_y) = store_config.transform_coords(chunk[2], chunk[3]);
+                let orientation = store_config.transform_orientation(chunk[4]);
+                if chunk[7] <= 0.0 {
+                    return [x, y, dest_x, dest_y, orientation, chunk[5], 0.0, 0.0];
+                }
+                update_single_robot(
+                    x,
+                    y,
+                    dest_x,
+                    dest_y,
+                    orientation,
+                    chunk[5],
+                    chunk[6],
+                    chunk[7],
+                    index,
+                    &store_config,
+                    delta,
+                    &mut rng,
+                    None,
+                    &mut TickOutcome::default(),
+                )
+            })
+            .collect();
+        SHARED_RNG.with(|shared| *shared.borrow_mut() = rng);
+        results
+    });
+    separate_robots(&mut results, &store_config);
+
+    let battery = BatteryModel::default();
+    let mut output = ROBOT_LAYOUT.with_header(results.len());
+    for (result, input) in results.iter().zip(robots.chunks_exact(ROBOT_STRIDE)) {
+        let (in_x, in_y) = store_config.transform_coords(input[0], input[1]);
+        let driven = (result[0] - in_x).hypot(result[1] - in_y);
         let (out_x, out_y) = store_config.transform_coords(result[0], result[1]);
         let (out_dest_x, out_dest_y) = store_config.transform_coords(result[2], result[3]);
         let out_orientation = store_config.transform_orientation(result[4]);
@@ -110,70 +274,217 @@ pub fn update_robots(robots: &[f32], products: &[f32], config: &[f32], delta_ms:
             out_orientation,
             result[5],
             result[6],
+            battery.drain(result[7], driven, delta),
         ]);
     }
 
-    output
+    Ok(output)
 }
 
 /// Compute a path from start to end, optionally preferring outer walkways
-/// Config format: [storeWidth, storeHeight, rowCount, rowSpacing, rowThickness, startOffset, walkwayWidth, crossRowBuffer, outerWalkwayOffset, orientation]
-#[wasm_bindgen]
+/// Connectivity: 4 for orthogonal moves only, 8 to also allow diagonal moves
+/// Smooth: drop waypoints that are not needed for line of sight on the nav grid
+/// Products: [x1, y1, x2, y2, ...]; cells within collision range of a product are avoided
+/// Config format: [version, storeWidth, storeHeight, rowCount, rowSpacing, rowThickness, startOffset, walkwayWidth, crossRowBuffer, outerWalkwayOffset, orientation]
+/// Output: [reachable, x1, y1, x2, y2, ...]; when the goal is unreachable (reachable = 0) the
+/// waypoints are only a straight-line fallback
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn compute_path(
     start: &[f32],
     end: &[f32],
     config: &[f32],
+    products: &[f32],
     prefer_outer_walkway: bool,
-) -> Vec<f32> {
-    if start.len() < 2 || end.len() < 2 {
-        return Vec::new();
-    }
-    let store_config = StoreConfig::from_buffer(config);
+    connectivity: u8,
+    smooth: bool,
+) -> Result<Vec<f32>, ExportError> {
+    require_len("start", start, 2)?;
+    require_len("end", end, 2)?;
+    require_stride("products", products, 2)?;
+    let store_config = StoreConfig::parse(config)?;
 
     let start_pt = store_config.transform_coords(start[0], start[1]);
     let end_pt = store_config.transform_coords(end[0], end[1]);
-
-    let path = if prefer_outer_walkway {
-        compute_path_with_outer_walkway(start_pt, end_pt, &store_config)
-    } else {
-        find_path(start_pt, end_pt, &store_config)
+    let options = PathOptions {
+        prefer_outer_walkway,
+        connectivity: Connectivity::from_neighbor_count(connectivity),
+        smooth,
     };
 
-    let mut finalized = path;
-    if let Some(last) = finalized.last() {
-        let dx = last.0 - end_pt.0;
-        let dy = last.1 - end_pt.1;
-        if dx * dx + dy * dy > 0.25 {
-            finalized.push(end_pt);
-        }
-    } else {
-        finalized.push(end_pt);
-    }
+    let path = with_nav_grid(&store_config, products, |grid, search| {
+        search.plan(start_pt, end_pt, grid, options)
+    });
+
+    Ok(path.to_buffer(&store_config))
+}
+
+/// Plan conflict-free paths for a batch of robots with cooperative A*
+/// Robots are planned in input order; later robots route around earlier ones and may wait
+/// Input per robot: [startX, startY, goalX, goalY]
+/// Products: [x1, y1, x2, y2, ...]; cells within collision range of a product are avoided
+/// Config format: [version, storeWidth, storeHeight, rowCount, rowSpacing, rowThickness, startOffset, walkwayWidth, crossRowBuffer, outerWalkwayOffset, orientation]
+/// Output per robot: [conflictFree, stepCount, x0, y0, x1, y1, ...], one waypoint per time step
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn compute_paths(
+    requests: &[f32],
+    config: &[f32],
+    products: &[f32],
+    connectivity: u8,
+) -> Result<Vec<f32>, ExportError> {
+    require_stride("requests", requests, 4)?;
+    require_stride("products", products, 2)?;
+    let store_config = StoreConfig::parse(config)?;
+    let requests = unpack_path_requests(requests, &store_config);
+    let connectivity = Connectivity::from_neighbor_count(connectivity);
+
+    let paths = with_nav_grid(&store_config, products, |grid, search| {
+        plan_cooperative_paths(&requests, grid, connectivity, search)
+    });
+
+    Ok(pack_timed_paths(&paths, &store_config))
+}
+
+/// Assign idle robots to pending pickups, minimising the total nav-grid distance driven
+/// Robots: [x1, y1, x2, y2, ...] for each idle robot
+/// Tasks: [x1, y1, x2, y2, ...] pickup point for each pending task
+/// Products: [x1, y1, x2, y2, ...]; cells within collision range of a product are avoided
+/// Config format: [version, storeWidth, storeHeight, rowCount, rowSpacing, rowThickness, startOffset, walkwayWidth, crossRowBuffer, outerWalkwayOffset, orientation]
+/// Output per assignment: [robotIndex, taskIndex, cost, waypointCount, x1, y1, ...], ordered by robot
+/// Robots or tasks left over (or with no reachable pairing) are simply absent from the output
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn allocate_tasks(
+    robots: &[f32],
+    tasks: &[f32],
+    config: &[f32],
+    products: &[f32],
+    connectivity: u8,
+) -> Result<Vec<f32>, ExportError> {
+    require_stride("robots", robots, 2)?;
+    require_stride("tasks", tasks, 2)?;
+    require_stride("products", products, 2)?;
+    let store_config = StoreConfig::parse(config)?;
+    let robots = unpack_points(robots, &store_config);
+    let tasks = unpack_points(tasks, &store_config);
+    let connectivity = Connectivity::from_neighbor_count(connectivity);
+
+    let assignments = with_nav_grid(&store_config, products, |grid, search| {
+        allocation::allocate_tasks(&robots, &tasks, grid, connectivity, search)
+    });
 
-    finalized
-        .into_iter()
-        .flat_map(|(x, y)| {
-            let (tx, ty) = store_config.transform_coords(x, y);
-            vec![tx, ty]
+    Ok(pack_task_assignments(
+        &assignments,
+        |robot| robot as f32,
+        &store_config,
+    ))
+}
+
+/// Find where a robot should stop to pick a product: the closest reachable walkway cell next to
+/// the product's shelf, facing the shelf. Both sides of the shelf are considered.
+/// Robot: [x, y]; Product: [x, y]
+/// Products: [x1, y1, x2, y2, ...]; cells within collision range of a product are avoided
+/// Config format: [version, storeWidth, storeHeight, rowCount, rowSpacing, rowThickness, startOffset, walkwayWidth, crossRowBuffer, outerWalkwayOffset, orientation]
+/// Output: [reachable, x, y, orientation]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn pickup_pose_for_product(
+    robot: &[f32],
+    product: &[f32],
+    config: &[f32],
+    products: &[f32],
+    connectivity: u8,
+) -> Result<Vec<f32>, ExportError> {
+    require_len("robot", robot, 2)?;
+    require_len("product", product, 2)?;
+    require_stride("products", products, 2)?;
+    let store_config = StoreConfig::parse(config)?;
+    let from = store_config.transform_coords(robot[0], robot[1]);
+    let target = store_config.transform_coords(product[0], product[1]);
+    let connectivity = Connectivity::from_neighbor_count(connectivity);
+
+    let pose = with_nav_grid(&store_config, products, |grid, search| {
+        pickup_pose(target, from, grid, connectivity, search)
+    });
+    Ok(pose.to_buffer(&store_config))
+}
+
+/// Plan one trip that picks several products and then goes to a drop-off, visiting the
+/// products in the order that keeps aisle travel short
+/// Robot: [x, y]; Drop target: [x, y]
+/// Product ids: positions in the products buffer, in any order
+/// Products: [x1, y1, x2, y2, ...]; cells within collision range of a product are avoided
+/// Config format: [version, storeWidth, storeHeight, rowCount, rowSpacing, rowThickness, startOffset, walkwayWidth, crossRowBuffer, outerWalkwayOffset, orientation]
+/// Output: [reachable, stopCount, (productId, waypointIndex) per stop in visiting order, x1, y1, ...]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn plan_pick_route(
+    robot: &[f32],
+    product_ids: &[u32],
+    drop_target: &[f32],
+    config: &[f32],
+    products: &[f32],
+    connectivity: u8,
+) -> Result<Vec<f32>, ExportError> {
+    require_len("robot", robot, 2)?;
+    require_len("drop target", drop_target, 2)?;
+    require_stride("products", products, 2)?;
+    let store_config = StoreConfig::parse(config)?;
+    let stops = product_ids
+        .iter()
+        .map(|id| {
+            let offset = *id as usize * 2;
+            products
+                .get(offset..offset + 2)
+                .map(|point| store_config.transform_coords(point[0], point[1]))
+                .ok_or(SimError::UnknownProduct(*id))
         })
-        .collect()
+        .collect::<Result<Vec<_>, _>>()?;
+    let start = store_config.transform_coords(robot[0], robot[1]);
+    let drop_off = store_config.transform_coords(drop_target[0], drop_target[1]);
+    let connectivity = Connectivity::from_neighbor_count(connectivity);
+
+    let route = with_nav_grid(&store_config, products, |grid, search| {
+        picking::plan_pick_route(start, &stops, drop_off, grid, connectivity, search)
+    });
+
+    Ok(pack_pick_route(
+        &route,
+        |stop| product_ids[stop] as f32,
+        &store_config,
+    ))
 }
 
 /// Move a single robot towards a target waypoint
 /// Input: [x, y, destX, destY, orientation, speed, lastMoveTime, waypointX, waypointY, deltaMs]
-/// Config format: [storeWidth, storeHeight, rowCount, rowSpacing, rowThickness, startOffset, walkwayWidth, crossRowBuffer, outerWalkwayOffset, orientation]
-/// Output: [newX, newY, orientation]
-#[wasm_bindgen]
-pub fn move_robot_to_waypoint(robot_data: &[f32], config: &[f32]) -> Vec<f32> {
-    if robot_data.len() < 10 {
-        return Vec::new();
-    }
+/// Optionally followed by [velocity, variant] (variant: 0 walking, 1 tracked, 2 dome) to drive
+/// the robot with that variant's acceleration and turn-rate limits instead of snapping to speed
+/// Config format: [version, storeWidth, storeHeight, rowCount, rowSpacing, rowThickness, startOffset, walkwayWidth, crossRowBuffer, outerWalkwayOffset, orientation]
+/// Output: [newX, newY, orientation], plus velocity when the kinematic fields were given
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn move_robot_to_waypoint(robot_data: &[f32], config: &[f32]) -> Result<Vec<f32>, ExportError> {
+    require_len("robot", robot_data, 10)?;
 
-    let store_config = StoreConfig::from_buffer(config);
+    let store_config = StoreConfig::parse(config)?;
     let (x, y) = store_config.transform_coords(robot_data[0], robot_data[1]);
     let orientation = store_config.transform_orientation(robot_data[4]);
     let (waypoint_x, waypoint_y) =
         store_config.transform_coords(robot_data[7], robot_data[8]);
+
+    if robot_data.len() >= 12 {
+        let mut drive = Drive::new(RobotVariant::from_index(robot_data[11] as u8));
+        drive.velocity = robot_data[10];
+        let result = move_to_waypoint_kinematic(
+            x,
+            y,
+            orientation,
+            robot_data[5],
+            waypoint_x,
+            waypoint_y,
+            robot_data[9],
+            &mut drive,
+        );
+        let (out_x, out_y) = store_config.transform_coords(result[0], result[1]);
+        let out_orientation = store_config.transform_orientation(result[2]);
+        return Ok(vec![out_x, out_y, out_orientation, result[3]]);
+    }
+
     let result = move_to_waypoint(
         x,
         y,
@@ -186,75 +497,154 @@ pub fn move_robot_to_waypoint(robot_data: &[f32], config: &[f32]) -> Vec<f32> {
 
     let (out_x, out_y) = store_config.transform_coords(result[0], result[1]);
     let out_orientation = store_config.transform_orientation(result[2]);
-    vec![out_x, out_y, out_orientation]
+    Ok(vec![out_x, out_y, out_orientation])
 }
 
 /// Move a single robot towards a target waypoint with product collision checks
 /// Input: [x, y, destX, destY, orientation, speed, lastMoveTime, waypointX, waypointY, deltaMs]
-/// Config format: [storeWidth, storeHeight, rowCount, rowSpacing, rowThickness, startOffset, walkwayWidth, crossRowBuffer, outerWalkwayOffset, orientation]
+/// Config format: [version, storeWidth, storeHeight, rowCount, rowSpacing, rowThickness, startOffset, walkwayWidth, crossRowBuffer, outerWalkwayOffset, orientation]
 /// Output: [newX, newY, orientation]
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub fn move_robot_to_waypoint_with_products(
     robot_data: &[f32],
     products: &[f32],
     config: &[f32],
-) -> Vec<f32> {
-    if robot_data.len() < 10 {
-        return Vec::new();
-    }
+) -> Result<Vec<f32>, ExportError> {
+    require_len("robot", robot_data, 10)?;
+    require_stride("products", products, 2)?;
 
-    let store_config = StoreConfig::from_buffer(config);
+    let store_config = StoreConfig::parse(config)?;
     let (x, y) = store_config.transform_coords(robot_data[0], robot_data[1]);
     let orientation = store_config.transform_orientation(robot_data[4]);
     let (waypoint_x, waypoint_y) =
         store_config.transform_coords(robot_data[7], robot_data[8]);
-    let transformed_products = if matches!(store_config.orientation, Orientation::Horizontal) {
-        let mut out = Vec::with_capacity(products.len());
-        for chunk in products.chunks_exact(2) {
-            let (px, py) = store_config.transform_coords(chunk[0], chunk[1]);
-            out.push(px);
-            out.push(py);
-        }
-        Some(out)
-    } else {
-        None
-    };
-    let products_ref = transformed_products.as_deref().unwrap_or(products);
-
-    let result = move_to_waypoint_with_collision(
-        x,
-        y,
-        orientation,
-        robot_data[5],
-        waypoint_x,
-        waypoint_y,
-        robot_data[9],
-        products_ref,
-        &store_config,
-    );
+    let result = with_product_index(&store_config, products, |index| {
+        move_to_waypoint_with_collision(
+            x,
+            y,
+            orientation,
+            robot_data[5],
+            waypoint_x,
+            waypoint_y,
+            robot_data[9],
+            index,
+            &store_config,
+        )
+    });
 
     let (out_x, out_y) = store_config.transform_coords(result[0], result[1]);
     let out_orientation = store_config.transform_orientation(result[2]);
-    vec![out_x, out_y, out_orientation]
+    Ok(vec![out_x, out_y, out_orientation])
 }
 
 /// Check if a robot has arrived at its waypoint
 /// Input: [robotX, robotY, waypointX, waypointY]
-/// Config format: [storeWidth, storeHeight, rowCount, rowSpacing, rowThickness, startOffset, walkwayWidth, crossRowBuffer, outerWalkwayOffset, orientation]
+/// Config format: [version, storeWidth, storeHeight, rowCount, rowSpacing, rowThickness, startOffset, walkwayWidth, crossRowBuffer, outerWalkwayOffset, orientation]
 /// Output: 1.0 if arrived, 0.0 if not
-#[wasm_bindgen]
-pub fn has_arrived_at_waypoint(positions: &[f32], config: &[f32]) -> f32 {
-    if positions.len() < 4 {
-        return 0.0;
-    }
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn has_arrived_at_waypoint(positions: &[f32], config: &[f32]) -> Result<f32, ExportError> {
+    require_len("positions", positions, 4)?;
 
-    let store_config = StoreConfig::from_buffer(config);
+    let store_config = StoreConfig::parse(config)?;
     let (robot_x, robot_y) = store_config.transform_coords(positions[0], positions[1]);
     let (waypoint_x, waypoint_y) = store_config.transform_coords(positions[2], positions[3]);
 
     if check_arrival(robot_x, robot_y, waypoint_x, waypoint_y) {
-        1.0
+        Ok(1.0)
     } else {
-        0.0
+        Ok(0.0)
+    }
+}
+
+/// Follow a whole path with pure pursuit instead of stopping at each waypoint
+/// Path format: [x1, y1, x2, y2, ...] as returned by `compute_path`
+/// Input: [x, y, orientation, speed, velocity, variant, deltaMs, distanceAlong]
+/// distanceAlong is the progress returned by the previous call (0 for a new path)
+/// Lookahead: distance ahead along the path to steer towards, <= 0 for the default
+/// Config format: [version, storeWidth, storeHeight, rowCount, rowSpacing, rowThickness, startOffset, walkwayWidth, crossRowBuffer, outerWalkwayOffset, orientation]
+/// Output: [newX, newY, orientation, velocity, distanceAlong, distanceRemaining, done]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn follow_path(
+    path: &[f32],
+    robot_data: &[f32],
+    config: &[f32],
+    lookahead: f32,
+) -> Result<Vec<f32>, ExportError> {
+    require_len("path", path, 2)?;
+    require_stride("path", path, 2)?;
+    require_len("robot", robot_data, 8)?;
+
+    let store_config = StoreConfig::parse(config)?;
+    let polyline = Polyline::new(
+        path.chunks_exact(2)
+            .map(|point| store_config.transform_coords(point[0], point[1]))
+            .collect(),
+    );
+    let (x, y) = store_config.transform_coords(robot_data[0], robot_data[1]);
+    let state = FollowState {
+        x,
+        y,
+        heading: store_config.transform_orientation(robot_data[2]),
+        velocity: robot_data[4],
+        progress: robot_data[7],
+    };
+    let limits = RobotVariant::from_index(robot_data[5] as u8).limits();
+    let lookahead = if lookahead > 0.0 {
+        lookahead
+    } else {
+        PATH_LOOKAHEAD_DEFAULT
+    };
+
+    let result = pure_pursuit_step(
+        &polyline,
+        state,
+        robot_data[3],
+        lookahead,
+        &limits,
+        robot_data[6],
+    );
+    let (out_x, out_y) = store_config.transform_coords(result.state.x, result.state.y);
+    Ok(vec![
+        out_x,
+        out_y,
+        store_config.transform_orientation(result.state.heading),
+        result.state.velocity,
+        result.state.progress,
+        result.remaining,
+        if result.done { 1.0 } else { 0.0 },
+    ])
+}
+
+/// Check a store layout for inconsistencies before it is used or saved
+/// Config format: [version, storeWidth, storeHeight, rowCount, rowSpacing, rowThickness, startOffset, walkwayWidth, crossRowBuffer, outerWalkwayOffset, orientation]
+/// Output: one `{ code, message }` per problem, empty when the layout is consistent
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn validate_config(config: &[f32]) -> Result<Vec<ConfigProblem>, ExportError> {
+    Ok(StoreConfig::parse(config)?.validate())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn product_caches_tell_apart_buffers_sharing_a_version() {
+        let config = StoreConfig::from_buffer(&[]);
+        let blocked = |products: &[f32]| {
+            with_nav_grid(&config, products, |grid, _| {
+                let (col, row) = grid.cell_at(-45.0, 11.0);
+                !grid.is_walkable(col, row)
+            })
+        };
+        let first_product =
+            |products: &[f32]| with_product_index(&config, products, |index| index.position(0));
+
+        set_products_version(1);
+        assert!(!blocked(&[]));
+        assert!(blocked(&[-45.0, 11.0]));
+        assert!(!blocked(&[]));
+        // Same length, different contents
+        assert_eq!(first_product(&[-45.0, 11.0]), Some((-45.0, 11.0)));
+        assert_eq!(first_product(&[15.0, 5.0]), Some((15.0, 5.0)));
     }
 }
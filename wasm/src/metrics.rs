@@ -0,0 +1,167 @@
+use crate::error::SimError;
+use crate::snapshot::{SnapshotReader, SnapshotWriter};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// How a robot spent one tick
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Activity {
+    Moving,
+    /// Standing still while it had somewhere to go
+    Blocked,
+    /// Parked, or waiting at the end of its path
+    Idle,
+}
+
+impl Activity {
+    /// Classify a tick by the distance driven and whether the robot had nowhere to go
+    pub fn classify(driven: f32, idle: bool) -> Self {
+        if driven > 0.01 {
+            Activity::Moving
+        } else if idle {
+            Activity::Idle
+        } else {
+            Activity::Blocked
+        }
+    }
+}
+
+/// Running totals for one robot since it was added or stats were last reset.
+/// Every tick counts towards exactly one of moving, blocked or idle time.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RobotStats {
+    /// World units driven
+    pub distance: f32,
+    /// Time spent driving
+    pub moving_ms: f64,
+    /// Time spent standing still while it had somewhere to go, e.g. held back by other robots
+    pub blocked_ms: f64,
+    /// Time spent parked (charging, waiting for a charger, flat) or waiting at the end of
+    /// its path
+    pub idle_ms: f64,
+    /// Ticks in which it was pushed apart from another robot or bounced off a product
+    pub collisions_avoided: u32,
+    /// Paths from `set_robot_path` followed to the end
+    pub tasks_completed: u32,
+    /// Summed time from path assignment to arrival over completed tasks
+    pub cycle_ms_total: f64,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl RobotStats {
+    /// Mean time from path assignment to arrival, 0 before the first completed task
+    pub fn mean_cycle_ms(&self) -> f64 {
+        if self.tasks_completed == 0 {
+            0.0
+        } else {
+            self.cycle_ms_total / self.tasks_completed as f64
+        }
+    }
+}
+
+impl RobotStats {
+    /// Encoded size of `write`
+    pub const SNAPSHOT_SIZE: usize = 4 + 8 * 3 + 4 + 4 + 8;
+
+    /// Add a tick that moved the robot `driven` world units
+    pub fn record_tick(&mut self, activity: Activity, driven: f32, delta_ms: f32) {
+        let delta_ms = delta_ms as f64;
+        match activity {
+            Activity::Moving => {
+                self.distance += driven;
+                self.moving_ms += delta_ms;
+            }
+            Activity::Blocked => self.blocked_ms += delta_ms,
+            Activity::Idle => self.idle_ms += delta_ms,
+        }
+    }
+
+    pub fn complete_task(&mut self, cycle_ms: f64) {
+        self.tasks_completed += 1;
+        self.cycle_ms_total += cycle_ms;
+    }
+
+    /// Fold another robot's totals into these
+    pub fn add(&mut self, other: &RobotStats) {
+        self.distance += other.distance;
+        self.moving_ms += other.moving_ms;
+        self.blocked_ms += other.blocked_ms;
+        self.idle_ms += other.idle_ms;
+        self.collisions_avoided += other.collisions_avoided;
+        self.tasks_completed += other.tasks_completed;
+        self.cycle_ms_total += other.cycle_ms_total;
+    }
+
+    pub fn write(&self, out: &mut SnapshotWriter) {
+        out.f32(self.distance);
+        out.f64(self.moving_ms);
+        out.f64(self.blocked_ms);
+        out.f64(self.idle_ms);
+        out.u32(self.collisions_avoided);
+        out.u32(self.tasks_completed);
+        out.f64(self.cycle_ms_total);
+    }
+
+    pub fn read(input: &mut SnapshotReader) -> Result<Self, SimError> {
+        Ok(RobotStats {
+            distance: input.f32()?,
+            moving_ms: input.f64()?,
+            blocked_ms: input.f64()?,
+            idle_ms: input.f64()?,
+            collisions_avoided: input.u32()?,
+            tasks_completed: input.u32()?,
+            cycle_ms_total: input.f64()?,
+        })
+    }
+}
+
+/// Totals over the whole fleet, including robots removed since stats were last reset, plus
+/// the rates derived from them
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FleetStats {
+    /// Robots currently in the simulation
+    pub robots: u32,
+    /// Simulated time covered by these stats
+    pub elapsed_ms: f64,
+    pub distance: f32,
+    pub moving_ms: f64,
+    pub blocked_ms: f64,
+    pub idle_ms: f64,
+    pub collisions_avoided: u32,
+    pub tasks_completed: u32,
+    /// Completed tasks per simulated hour
+    pub tasks_per_hour: f64,
+    /// Mean time from path assignment to arrival, 0 before the first completed task
+    pub mean_cycle_ms: f64,
+    /// Share of robot time spent moving, from 0 to 1
+    pub utilisation: f64,
+}
+
+impl FleetStats {
+    pub fn new(robots: u32, elapsed_ms: f64, totals: &RobotStats) -> Self {
+        let robot_ms = totals.moving_ms + totals.blocked_ms + totals.idle_ms;
+        FleetStats {
+            robots,
+            elapsed_ms,
+            distance: totals.distance,
+            moving_ms: totals.moving_ms,
+            blocked_ms: totals.blocked_ms,
+            idle_ms: totals.idle_ms,
+            collisions_avoided: totals.collisions_avoided,
+            tasks_completed: totals.tasks_completed,
+            tasks_per_hour: if elapsed_ms > 0.0 {
+                totals.tasks_completed as f64 * 3_600_000.0 / elapsed_ms
+            } else {
+                0.0
+            },
+            mean_cycle_ms: totals.mean_cycle_ms(),
+            utilisation: if robot_ms > 0.0 {
+                totals.moving_ms / robot_ms
+            } else {
+                0.0
+            },
+        }
+    }
+}
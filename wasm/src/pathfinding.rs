@@ -1,8 +1,7 @@
-use crate::constants::*;
-use crate::geometry::clamp;
+use crate::constants::{ROBOT_RADIUS, StoreConfig};
 use crate::grid::*;
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::collections::BinaryHeap;
 
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub struct Node {
@@ -23,59 +22,81 @@ impl PartialOrd for Node {
     }
 }
 
-fn heuristic(a: (i32, i32), b: (i32, i32)) -> i32 {
-    (a.0 - b.0).abs() + (a.1 - b.1).abs()
+/// Cost of an orthogonal step; diagonal steps cost `DIAGONAL_COST` (≈ √2 scaled the same way)
+pub const STRAIGHT_COST: i32 = 10;
+pub const DIAGONAL_COST: i32 = 14;
+
+/// Which moves A* may take between nav cells
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Connectivity {
+    #[default]
+    Four,
+    Eight,
 }
 
-fn neighbors(col: i32, row: i32, grid: &[Vec<bool>]) -> Vec<(i32, i32)> {
-    let mut list = Vec::new();
-    let max_col = grid.first().map(|r| r.len() as i32).unwrap_or(0) - 1;
-    let max_row = grid.len() as i32 - 1;
-    if col > 0 {
-        list.push((col - 1, row))
-    }
-    if col < max_col {
-        list.push((col + 1, row))
-    }
-    if row > 0 {
-        list.push((col, row - 1))
+impl Connectivity {
+    /// Map a neighbour count from the JS side (4 or 8) to a connectivity, defaulting to 4
+    pub fn from_neighbor_count(count: u8) -> Self {
+        if count == 8 {
+            Connectivity::Eight
+        } else {
+            Connectivity::Four
+        }
     }
-    if row < max_row {
-        list.push((col, row + 1))
+}
+
+pub fn heuristic(a: (i32, i32), b: (i32, i32), connectivity: Connectivity) -> i32 {
+    let dx = (a.0 - b.0).abs();
+    let dy = (a.1 - b.1).abs();
+    match connectivity {
+        Connectivity::Four => STRAIGHT_COST * (dx + dy),
+        // Octile distance
+        Connectivity::Eight => {
+            STRAIGHT_COST * (dx + dy) + (DIAGONAL_COST - 2 * STRAIGHT_COST) * dx.min(dy)
+        }
     }
-    list.into_iter()
-        .filter(|(c, r)| {
-            grid.get(*r as usize)
-                .and_then(|row_vec| row_vec.get(*c as usize))
-                .copied()
-                .unwrap_or(false)
+}
+
+const ORTHOGONAL_STEPS: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const DIAGONAL_STEPS: [(i32, i32); 4] = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+
+/// Walkable neighbours with their step cost. Diagonal moves are only allowed when both
+/// orthogonal cells they pass between are walkable, so robots never clip a shelf corner.
+pub fn neighbors(
+    col: i32,
+    row: i32,
+    grid: &NavGrid,
+    connectivity: Connectivity,
+) -> impl Iterator<Item = (i32, i32, i32)> + '_ {
+    let orthogonal = ORTHOGONAL_STEPS
+        .into_iter()
+        .map(move |(dc, dr)| (col + dc, row + dr, STRAIGHT_COST))
+        .filter(|(c, r, _)| grid.is_walkable(*c, *r));
+    let diagonal = DIAGONAL_STEPS
+        .into_iter()
+        .filter(move |_| connectivity == Connectivity::Eight)
+        .filter(move |(dc, dr)| {
+            grid.is_walkable(col + dc, row + dr)
+                && grid.is_walkable(col + dc, row)
+                && grid.is_walkable(col, row + dr)
         })
-        .collect()
+        .map(move |(dc, dr)| (col + dc, row + dr, DIAGONAL_COST));
+    orthogonal.chain(diagonal)
 }
 
-fn find_nearest_walkable(col: i32, row: i32, grid: &[Vec<bool>]) -> (i32, i32) {
-    if grid
-        .get(row as usize)
-        .and_then(|r| r.get(col as usize))
-        .copied()
-        .unwrap_or(false)
-    {
+pub fn find_nearest_walkable(col: i32, row: i32, grid: &NavGrid) -> (i32, i32) {
+    if grid.is_walkable(col, row) {
         return (col, row);
     }
-    let mut visited = HashSet::new();
+    let mut visited = vec![false; grid.len()];
     let mut queue = vec![(col, row)];
-    let max_col = grid.first().map(|v| v.len() as i32).unwrap_or(0);
-    let max_row = grid.len() as i32;
     while let Some((c, r)) = queue.pop() {
-        if !visited.insert((c, r)) {
+        let idx = grid.index(c, r);
+        if visited[idx] {
             continue;
         }
-        if grid
-            .get(r as usize)
-            .and_then(|row_vec| row_vec.get(c as usize))
-            .copied()
-            .unwrap_or(false)
-        {
+        visited[idx] = true;
+        if grid.is_walkable(c, r) {
             return (c, r);
         }
         // Push in reverse priority order (last pushed = first popped)
@@ -83,139 +104,403 @@ fn find_nearest_walkable(col: i32, row: i32, grid: &[Vec<bool>]) -> (i32, i32) {
         if r > 0 {
             queue.push((c, r - 1))
         }
-        if r + 1 < max_row {
+        if r + 1 < grid.rows() {
             queue.push((c, r + 1))
         }
         if c > 0 {
             queue.push((c - 1, r))
         }
-        if c + 1 < max_col {
+        if c + 1 < grid.cols() {
             queue.push((c + 1, r))
         }
     }
     (col, row)
 }
 
-pub fn find_path(start: (f32, f32), end: (f32, f32), config: &StoreConfig) -> Vec<(f32, f32)> {
-    let grid = build_nav_grid(config);
-    let max_col = grid.first().map(|r| r.len() as i32).unwrap_or(0) - 1;
-    let max_row = grid.len() as i32 - 1;
-    if max_col < 0 || max_row < 0 {
-        return vec![start, end];
-    }
-
-    let mut start_col = world_to_col(start.0, config.store_width);
-    let mut start_row = world_to_row(start.1, config.store_height);
-    start_col = clamp(start_col as f32, 0.0, max_col as f32) as i32;
-    start_row = clamp(start_row as f32, 0.0, max_row as f32) as i32;
-
-    let mut end_col = world_to_col(end.0, config.store_width);
-    let mut end_row = world_to_row(end.1, config.store_height);
-    end_col = clamp(end_col as f32, 0.0, max_col as f32) as i32;
-    end_row = clamp(end_row as f32, 0.0, max_row as f32) as i32;
-
-    let (start_col, start_row) = find_nearest_walkable(start_col, start_row, &grid);
-    let (end_col, end_row) = find_nearest_walkable(end_col, end_row, &grid);
-
-    let mut open = BinaryHeap::new();
-    let mut came_from: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
-    let mut g_score: HashMap<(i32, i32), i32> = HashMap::new();
-    let mut f_score: HashMap<(i32, i32), i32> = HashMap::new();
-
-    g_score.insert((start_col, start_row), 0);
-    f_score.insert(
-        (start_col, start_row),
-        heuristic((start_col, start_row), (end_col, end_row)),
-    );
-    open.push(Node {
-        col: start_col,
-        row: start_row,
-        f_score: *f_score.get(&(start_col, start_row)).unwrap_or(&i32::MAX),
-    });
-
-    while let Some(current) = open.pop() {
-        if current.col == end_col && current.row == end_row {
-            let mut path = Vec::new();
-            let mut curr = (current.col, current.row);
-            path.push((
-                cell_center_x(curr.0, config.store_width),
-                cell_center_y(curr.1, config.store_height),
-            ));
-            while let Some(prev) = came_from.get(&curr) {
-                curr = *prev;
-                path.push((
-                    cell_center_x(curr.0, config.store_width),
-                    cell_center_y(curr.1, config.store_height),
-                ));
+/// How a route should be planned on top of plain A*
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PathOptions {
+    pub prefer_outer_walkway: bool,
+    pub connectivity: Connectivity,
+    pub smooth: bool,
+}
+
+/// Waypoints of a planned route and whether A* actually reached the goal.
+/// Unreachable goals still carry a straight `[start, end]` fallback so callers can keep moving.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PathResult {
+    pub waypoints: Vec<(f32, f32)>,
+    pub reachable: bool,
+}
+
+impl PathResult {
+    fn fallback(start: (f32, f32), end: (f32, f32)) -> Self {
+        PathResult {
+            waypoints: vec![start, end],
+            reachable: false,
+        }
+    }
+
+    /// Output: [reachable, x1, y1, x2, y2, ...] with waypoints transformed back to world coordinates
+    pub fn to_buffer(&self, config: &StoreConfig) -> Vec<f32> {
+        let mut output = Vec::with_capacity(1 + self.waypoints.len() * 2);
+        output.push(if self.reachable { 1.0 } else { 0.0 });
+        for &(x, y) in &self.waypoints {
+            let (tx, ty) = config.transform_coords(x, y);
+            output.extend_from_slice(&[tx, ty]);
+        }
+        output
+    }
+}
+
+/// Reusable A* scratch space for running many path queries against the same `NavGrid`.
+/// Buffers are sized to the grid once and reset lazily with a generation counter.
+#[derive(Default)]
+pub struct PathSearch {
+    g_score: Vec<i32>,
+    came_from: Vec<usize>,
+    generation: Vec<u32>,
+    current_generation: u32,
+    open: BinaryHeap<Node>,
+}
+
+impl PathSearch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn reset(&mut self, cell_count: usize) {
+        if self.generation.len() != cell_count || self.current_generation == u32::MAX {
+            self.g_score = vec![0; cell_count];
+            self.came_from = vec![usize::MAX; cell_count];
+            self.generation = vec![0; cell_count];
+            self.current_generation = 0;
+        }
+        self.current_generation += 1;
+        self.open.clear();
+    }
+
+    fn g(&self, idx: usize) -> i32 {
+        if self.generation[idx] == self.current_generation {
+            self.g_score[idx]
+        } else {
+            i32::MAX / 2
+        }
+    }
+
+    fn record(&mut self, idx: usize, g: i32, parent: usize) {
+        self.generation[idx] = self.current_generation;
+        self.g_score[idx] = g;
+        self.came_from[idx] = parent;
+    }
+
+    /// Find a path between two store-frame points, returned as cell centres.
+    /// Falls back to an unreachable straight `[start, end]` line when A* cannot reach the goal.
+    pub fn find_path(
+        &mut self,
+        start: (f32, f32),
+        end: (f32, f32),
+        grid: &NavGrid,
+        connectivity: Connectivity,
+    ) -> PathResult {
+        if grid.is_empty() {
+            return PathResult::fallback(start, end);
+        }
+        self.reset(grid.len());
+
+        let (start_col, start_row) = grid.cell_at(start.0, start.1);
+        let (end_col, end_row) = grid.cell_at(end.0, end.1);
+        let (start_col, start_row) = find_nearest_walkable(start_col, start_row, grid);
+        let (end_col, end_row) = find_nearest_walkable(end_col, end_row, grid);
+
+        let start_idx = grid.index(start_col, start_row);
+        self.record(start_idx, 0, usize::MAX);
+        self.open.push(Node {
+            col: start_col,
+            row: start_row,
+            f_score: heuristic((start_col, start_row), (end_col, end_row), connectivity),
+        });
+
+        while let Some(current) = self.open.pop() {
+            let current_idx = grid.index(current.col, current.row);
+            if current.col == end_col && current.row == end_row {
+                let mut path = Vec::new();
+                let mut idx = current_idx;
+                loop {
+                    let col = (idx % grid.cols() as usize) as i32;
+                    let row = (idx / grid.cols() as usize) as i32;
+                    path.push(grid.cell_center(col, row));
+                    idx = self.came_from[idx];
+                    if idx == usize::MAX {
+                        break;
+                    }
+                }
+                path.reverse();
+                return PathResult {
+                    waypoints: path,
+                    reachable: true,
+                };
             }
-            path.reverse();
-            return path;
-        }
-
-        for (nc, nr) in neighbors(current.col, current.row, &grid) {
-            let tentative_g = g_score
-                .get(&(current.col, current.row))
-                .copied()
-                .unwrap_or(i32::MAX / 2)
-                + 1;
-            if tentative_g < *g_score.get(&(nc, nr)).unwrap_or(&(i32::MAX / 2)) {
-                came_from.insert((nc, nr), (current.col, current.row));
-                g_score.insert((nc, nr), tentative_g);
-                let f = tentative_g + heuristic((nc, nr), (end_col, end_row));
-                f_score.insert((nc, nr), f);
-                open.push(Node {
-                    col: nc,
-                    row: nr,
-                    f_score: f,
-                });
+
+            let current_g = self.g(current_idx);
+            for (nc, nr, step_cost) in neighbors(current.col, current.row, grid, connectivity) {
+                let neighbor_idx = grid.index(nc, nr);
+                let tentative_g = current_g + step_cost;
+                if tentative_g < self.g(neighbor_idx) {
+                    self.record(neighbor_idx, tentative_g, current_idx);
+                    self.open.push(Node {
+                        col: nc,
+                        row: nr,
+                        f_score: tentative_g
+                            + heuristic((nc, nr), (end_col, end_row), connectivity),
+                    });
+                }
             }
         }
+
+        PathResult::fallback(start, end)
     }
 
-    vec![start, end]
+    /// Plan a complete route: A* (optionally via an outer walkway), the exact goal appended,
+    /// and string-pulling when `options.smooth` is set
+    pub fn plan(
+        &mut self,
+        start: (f32, f32),
+        end: (f32, f32),
+        grid: &NavGrid,
+        options: PathOptions,
+    ) -> PathResult {
+        let path = if options.prefer_outer_walkway {
+            self.find_path_via_outer_walkway(start, end, grid, options.connectivity)
+        } else {
+            self.find_path(start, end, grid, options.connectivity)
+        };
+        let waypoints = append_goal(path.waypoints, end);
+        let waypoints = if options.smooth {
+            smooth_path(&waypoints, grid)
+        } else {
+            waypoints
+        };
+        PathResult {
+            waypoints,
+            reachable: path.reachable,
+        }
+    }
+
+    /// Route through whichever outer walkway (top or bottom) gives the shorter trip,
+    /// preferring a walkway whose legs are all reachable
+    pub fn find_path_via_outer_walkway(
+        &mut self,
+        start: (f32, f32),
+        end: (f32, f32),
+        grid: &NavGrid,
+        connectivity: Connectivity,
+    ) -> PathResult {
+        let config = grid.config();
+        let top_y = config.store_height / 2.0 - config.outer_walkway_offset;
+        let bottom_y = -config.store_height / 2.0 + config.outer_walkway_offset;
+
+        let mut build_route = |anchor_y: f32| {
+            let leg1 = self.find_path(start, (start.0, anchor_y), grid, connectivity);
+            let anchor_pt = *leg1.waypoints.last().unwrap_or(&(start.0, anchor_y));
+            let leg2 = self.find_path(anchor_pt, (end.0, anchor_y), grid, connectivity);
+            let leg2_anchor = *leg2.waypoints.last().unwrap_or(&(end.0, anchor_y));
+            let leg3 = self.find_path(leg2_anchor, end, grid, connectivity);
+            let reachable = leg1.reachable && leg2.reachable && leg3.reachable;
+            let mut stitched = Vec::new();
+            stitched.extend(leg1.waypoints);
+            if !leg2.waypoints.is_empty() {
+                stitched.extend(leg2.waypoints.into_iter().skip(1));
+            }
+            if !leg3.waypoints.is_empty() {
+                stitched.extend(leg3.waypoints.into_iter().skip(1));
+            }
+            PathResult {
+                waypoints: stitched,
+                reachable,
+            }
+        };
+
+        let top_route = build_route(top_y);
+        let bottom_route = build_route(bottom_y);
+
+        let top_is_shorter =
+            path_length(&top_route.waypoints) <= path_length(&bottom_route.waypoints);
+        if top_route.reachable == bottom_route.reachable {
+            if top_is_shorter {
+                top_route
+            } else {
+                bottom_route
+            }
+        } else if top_route.reachable {
+            top_route
+        } else {
+            bottom_route
+        }
+    }
 }
 
-pub fn compute_path_with_outer_walkway(
-    start: (f32, f32),
-    end: (f32, f32),
-    config: &StoreConfig,
-) -> Vec<(f32, f32)> {
-    let top_y = config.store_height / 2.0 - config.outer_walkway_offset;
-    let bottom_y = -config.store_height / 2.0 + config.outer_walkway_offset;
+pub fn path_length(path: &[(f32, f32)]) -> f32 {
+    let mut total = 0.0;
+    for i in 1..path.len() {
+        let dx = path[i].0 - path[i - 1].0;
+        let dy = path[i].1 - path[i - 1].1;
+        total += (dx * dx + dy * dy).sqrt();
+    }
+    total
+}
 
-    let build_route = |anchor_y: f32| {
-        let leg1 = find_path(start, (start.0, anchor_y), config);
-        let anchor_pt = *leg1.last().unwrap_or(&(start.0, anchor_y));
-        let leg2 = find_path(anchor_pt, (end.0, anchor_y), config);
-        let leg2_anchor = *leg2.last().unwrap_or(&(end.0, anchor_y));
-        let leg3 = find_path(leg2_anchor, end, config);
-        let mut stitched = Vec::new();
-        stitched.extend(leg1);
-        if !leg2.is_empty() {
-            stitched.extend(leg2.into_iter().skip(1));
+/// String-pull a path: drop every waypoint that the previous kept waypoint can see past,
+/// leaving only the corners needed to stay on walkable cells. Visibility is checked for the
+/// full robot width (`ROBOT_RADIUS` either side of the segment), not just its centreline.
+pub fn smooth_path(path: &[(f32, f32)], grid: &NavGrid) -> Vec<(f32, f32)> {
+    if path.len() <= 2 {
+        return path.to_vec();
+    }
+
+    let mut smoothed = vec![path[0]];
+    let mut anchor = path[0];
+    for i in 1..path.len() - 1 {
+        if !grid.has_clear_corridor(anchor, path[i + 1], ROBOT_RADIUS) {
+            anchor = path[i];
+            smoothed.push(anchor);
         }
-        if !leg3.is_empty() {
-            stitched.extend(leg3.into_iter().skip(1));
+    }
+    smoothed.push(path[path.len() - 1]);
+    smoothed
+}
+
+/// Append the exact goal to a cell-centre path unless the last waypoint already sits on it
+pub fn append_goal(mut path: Vec<(f32, f32)>, end: (f32, f32)) -> Vec<(f32, f32)> {
+    if let Some(last) = path.last() {
+        let dx = last.0 - end.0;
+        let dy = last.1 - end.1;
+        if dx * dx + dy * dy > 0.25 {
+            path.push(end);
         }
-        stitched
-    };
+    } else {
+        path.push(end);
+    }
+    path
+}
 
-    let top_route = build_route(top_y);
-    let bottom_route = build_route(bottom_y);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let distance = |path: &[(f32, f32)]| {
-        let mut total = 0.0;
-        for i in 1..path.len() {
-            let dx = path[i].0 - path[i - 1].0;
-            let dy = path[i].1 - path[i - 1].1;
-            total += (dx * dx + dy * dy).sqrt();
+    /// Default store grid with the shelves cleared, so tests can place their own obstacles
+    fn open_grid() -> NavGrid {
+        let mut grid = build_nav_grid(&StoreConfig::from_buffer(&[]));
+        for row in 0..grid.rows() {
+            for col in 0..grid.cols() {
+                grid.set_walkable(col, row, true);
+            }
         }
-        total
-    };
+        grid
+    }
 
-    if distance(&top_route) <= distance(&bottom_route) {
-        top_route
-    } else {
-        bottom_route
+    fn cell_steps(path: &[(f32, f32)], grid: &NavGrid) -> Vec<((i32, i32), (i32, i32))> {
+        path.windows(2)
+            .map(|pair| {
+                (
+                    grid.cell_at(pair[0].0, pair[0].1),
+                    grid.cell_at(pair[1].0, pair[1].1),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn four_connected_paths_step_orthogonally() {
+        let grid = open_grid();
+        let (start, end) = (grid.cell_center(2, 2), grid.cell_center(12, 9));
+        let path = PathSearch::new().find_path(start, end, &grid, Connectivity::Four);
+
+        assert!(path.reachable);
+        assert_eq!(path.waypoints.len(), 10 + 7 + 1);
+        for ((c0, r0), (c1, r1)) in cell_steps(&path.waypoints, &grid) {
+            assert_eq!((c1 - c0).abs() + (r1 - r0).abs(), 1);
+        }
+    }
+
+    #[test]
+    fn eight_connected_paths_cut_diagonally() {
+        let grid = open_grid();
+        let (start, end) = (grid.cell_center(2, 2), grid.cell_center(12, 9));
+        let path = PathSearch::new().find_path(start, end, &grid, Connectivity::Eight);
+
+        assert!(path.reachable);
+        assert_eq!(path.waypoints.len(), 10 + 1);
+        for ((c0, r0), (c1, r1)) in cell_steps(&path.waypoints, &grid) {
+            assert!((c1 - c0).abs() <= 1 && (r1 - r0).abs() <= 1);
+        }
+    }
+
+    #[test]
+    fn diagonal_moves_never_cut_a_blocked_corner() {
+        let mut grid = open_grid();
+        grid.set_walkable(5, 4, false);
+        let moves: Vec<_> = neighbors(4, 4, &grid, Connectivity::Eight)
+            .map(|(col, row, _)| (col, row))
+            .collect();
+
+        assert!(!moves.contains(&(5, 3)) && !moves.contains(&(5, 5)));
+        assert!(moves.contains(&(3, 3)) && moves.contains(&(3, 5)));
+    }
+
+    #[test]
+    fn eight_connected_paths_round_wall_ends_without_cutting_them() {
+        let mut grid = open_grid();
+        for row in 0..12 {
+            grid.set_walkable(10, row, false);
+        }
+        let (start, end) = (grid.cell_center(6, 2), grid.cell_center(14, 2));
+        let path = PathSearch::new().find_path(start, end, &grid, Connectivity::Eight);
+
+        assert!(path.reachable);
+        for ((c0, r0), (c1, r1)) in cell_steps(&path.waypoints, &grid) {
+            assert!(grid.is_walkable(c1, r1));
+            if c0 != c1 && r0 != r1 {
+                assert!(grid.is_walkable(c1, r0) && grid.is_walkable(c0, r1));
+            }
+        }
+    }
+
+    #[test]
+    fn smoothing_keeps_the_robot_clear_of_obstacles() {
+        let mut grid = open_grid();
+        for row in 0..12 {
+            grid.set_walkable(10, row, false);
+        }
+        let (start, end) = (grid.cell_center(6, 2), grid.cell_center(14, 2));
+        let options = PathOptions {
+            connectivity: Connectivity::Eight,
+            ..PathOptions::default()
+        };
+        let raw = PathSearch::new().plan(start, end, &grid, options);
+        let smooth = smooth_path(&raw.waypoints, &grid);
+
+        assert!(smooth.len() < raw.waypoints.len());
+        assert_eq!(smooth.first(), raw.waypoints.first());
+        assert_eq!(smooth.last(), raw.waypoints.last());
+        for pair in smooth.windows(2) {
+            assert!(grid.has_clear_corridor(pair[0], pair[1], ROBOT_RADIUS));
+        }
+    }
+
+    #[test]
+    fn smoothing_keeps_corners_a_robot_would_clip() {
+        let mut grid = open_grid();
+        let path = [
+            grid.cell_center(2, 4),
+            grid.cell_center(5, 5),
+            grid.cell_center(8, 6),
+        ];
+        assert_eq!(smooth_path(&path, &grid).len(), 2);
+
+        // The straight segment clears this cell's corner by less than the robot radius
+        grid.set_walkable(5, 6, false);
+        assert!(grid.has_line_of_sight(path[0], path[2]));
+        assert_eq!(smooth_path(&path, &grid), path);
     }
 }
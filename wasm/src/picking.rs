@@ -0,0 +1,293 @@
+use crate::constants::{NAV_CELL_SIZE, StoreConfig};
+use crate::grid::NavGrid;
+use crate::pathfinding::*;
+
+/// Added to the length of legs A* could not complete, so unreachable stops are visited last
+/// without making the tour maths degenerate
+const UNREACHABLE_PENALTY: f32 = 10_000.0;
+
+/// Multi-stop pick trip: the order the stops are visited in and the stitched route
+#[derive(Clone, Debug, Default)]
+pub struct PickRoute {
+    /// Indices into the `stops` passed to `plan_pick_route`, in visiting order
+    pub order: Vec<usize>,
+    /// For each entry of `order`, the index in `route.waypoints` where that stop is reached
+    pub stop_waypoints: Vec<usize>,
+    /// Start → stops → drop-off; `reachable` is false if any leg fell back to a straight line
+    pub route: PathResult,
+}
+
+/// Where a robot stops to pick a product: a walkway cell next to the shelf (store frame) and
+/// the heading that faces the shelf
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PickupPose {
+    pub x: f32,
+    pub y: f32,
+    pub heading: f32,
+    /// False if A* could not reach the pose from the robot
+    pub reachable: bool,
+}
+
+impl PickupPose {
+    /// Output: [reachable, x, y, orientation] in world coordinates
+    pub fn to_buffer(&self, config: &StoreConfig) -> Vec<f32> {
+        let (x, y) = config.transform_coords(self.x, self.y);
+        vec![
+            if self.reachable { 1.0 } else { 0.0 },
+            x,
+            y,
+            config.transform_orientation(self.heading),
+        ]
+    }
+}
+
+/// Closest walkway pose to pick `product` from, as seen from `from`. Both faces of the nearest
+/// shelf row are tried and the one with the shorter A* path wins; when neither can be reached
+/// the pose nearest in a straight line is returned with `reachable` false.
+pub fn pickup_pose(
+    product: (f32, f32),
+    from: (f32, f32),
+    grid: &NavGrid,
+    connectivity: Connectivity,
+    search: &mut PathSearch,
+) -> PickupPose {
+    let config = grid.config();
+    let (_, row) = grid.cell_at(product.0, product.1);
+    let mut candidates = Vec::with_capacity(2);
+    if config.row_count > 0 {
+        let face_offset = config.row_thickness / 2.0;
+        let max_steps = (config.row_spacing / NAV_CELL_SIZE).ceil() as i32;
+        let center = config.get_row_center(config.nearest_row(product.0));
+        for side in [-1.0f32, 1.0] {
+            // Walk away from the shelf face until the first walkable cell of the aisle
+            let (face_col, _) = grid.cell_at(center + side * face_offset, product.1);
+            let step = side as i32;
+            let walkable_col = (0..=max_steps)
+                .map(|offset| face_col + offset * step)
+                .take_while(|col| *col >= 0 && *col < grid.cols())
+                .find(|col| grid.is_walkable(*col, row));
+            if let Some(col) = walkable_col {
+                let (x, y) = grid.cell_center(col, row);
+                // Forward is (sin h, cos h); face back towards the shelf
+                candidates.push(((x, y), -side * std::f32::consts::FRAC_PI_2));
+            }
+        }
+    }
+    if candidates.is_empty() {
+        // No shelf to face (e.g. no rows): stop at the nearest walkable cell facing the product
+        let (col, row) = grid.cell_at(product.0, product.1);
+        let (col, row) = find_nearest_walkable(col, row, grid);
+        let (x, y) = grid.cell_center(col, row);
+        candidates.push(((x, y), (product.0 - x).atan2(product.1 - y)));
+    }
+
+    // Reachable poses before unreachable ones, then by path length (or straight-line distance)
+    candidates
+        .into_iter()
+        .map(|((x, y), heading)| {
+            let route = search.find_path(from, (x, y), grid, connectivity);
+            let distance = if route.reachable {
+                path_length(&route.waypoints)
+            } else {
+                (x - from.0).hypot(y - from.1)
+            };
+            let pose = PickupPose {
+                x,
+                y,
+                heading,
+                reachable: route.reachable,
+            };
+            (pose, distance)
+        })
+        .min_by(|(a, a_distance), (b, b_distance)| {
+            b.reachable
+                .cmp(&a.reachable)
+                .then(a_distance.total_cmp(b_distance))
+        })
+        .map(|(pose, _)| pose)
+        .expect("at least one pickup candidate")
+}
+
+/// Plan a trip from `start` through every stop to `drop_off`, ordering the stops to keep the
+/// aisle travel short: nearest-neighbour construction, then 2-opt with both ends fixed, over
+/// A* distances on `grid`. Legs are A* paths stitched together; the exact drop-off is appended.
+pub fn plan_pick_route(
+    start: (f32, f32),
+    stops: &[(f32, f32)],
+    drop_off: (f32, f32),
+    grid: &NavGrid,
+    connectivity: Connectivity,
+    search: &mut PathSearch,
+) -> PickRoute {
+    // Node 0 is the start, 1..=n the stops, n + 1 the drop-off
+    let mut nodes = Vec::with_capacity(stops.len() + 2);
+    nodes.push(start);
+    for stop in stops {
+        let pose = pickup_pose(*stop, start, grid, connectivity, search);
+        nodes.push((pose.x, pose.y));
+    }
+    nodes.push(drop_off);
+    let count = nodes.len();
+
+    let mut legs: Vec<Option<PathResult>> = vec![None; count * count];
+    let mut distance = vec![0.0f32; count * count];
+    for from in 0..count {
+        for to in (from + 1)..count {
+            if from == 0 && to == count - 1 && !stops.is_empty() {
+                // Start straight to drop-off is never a leg of a trip with stops
+                continue;
+            }
+            let leg = search.find_path(nodes[from], nodes[to], grid, connectivity);
+            let length = if leg.reachable {
+                path_length(&leg.waypoints)
+            } else {
+                path_length(&leg.waypoints) + UNREACHABLE_PENALTY
+            };
+            distance[from * count + to] = length;
+            distance[to * count + from] = length;
+            legs[from * count + to] = Some(leg);
+        }
+    }
+    let dist = |a: usize, b: usize| distance[a * count + b];
+
+    let mut sequence = nearest_neighbour_order(count, &dist);
+    two_opt(&mut sequence, &dist);
+
+    let mut waypoints: Vec<(f32, f32)> = Vec::new();
+    let mut stop_waypoints = Vec::with_capacity(stops.len());
+    let mut reachable = true;
+    for pair in sequence.windows(2) {
+        let (from, to) = (pair[0], pair[1]);
+        let (leg, reversed) = if from < to {
+            (legs[from * count + to].as_ref(), false)
+        } else {
+            (legs[to * count + from].as_ref(), true)
+        };
+        let Some(leg) = leg else {
+            continue;
+        };
+        reachable &= leg.reachable;
+        let mut leg_points = leg.waypoints.clone();
+        if reversed {
+            leg_points.reverse();
+        }
+        let skip = usize::from(!waypoints.is_empty());
+        waypoints.extend(leg_points.into_iter().skip(skip));
+        if to != count - 1 {
+            stop_waypoints.push(waypoints.len().saturating_sub(1));
+        }
+    }
+    PickRoute {
+        order: sequence[1..count - 1].iter().map(|node| node - 1).collect(),
+        stop_waypoints,
+        route: PathResult {
+            waypoints: append_goal(waypoints, drop_off),
+            reachable,
+        },
+    }
+}
+
+/// Greedy tour: from the start, always visit the closest unvisited stop, then the drop-off
+fn nearest_neighbour_order(count: usize, dist: &impl Fn(usize, usize) -> f32) -> Vec<usize> {
+    let mut sequence = Vec::with_capacity(count);
+    let mut visited = vec![false; count];
+    sequence.push(0);
+    visited[0] = true;
+    visited[count - 1] = true;
+    let mut current = 0;
+    for _ in 1..count - 1 {
+        let next = (1..count - 1)
+            .filter(|node| !visited[*node])
+            .min_by(|a, b| dist(current, *a).total_cmp(&dist(current, *b)))
+            .expect("an unvisited stop remains");
+        visited[next] = true;
+        sequence.push(next);
+        current = next;
+    }
+    sequence.push(count - 1);
+    sequence
+}
+
+/// Reverse inner segments while that shortens the tour; the first and last nodes stay put
+fn two_opt(sequence: &mut [usize], dist: &impl Fn(usize, usize) -> f32) {
+    let len = sequence.len();
+    if len < 4 {
+        return;
+    }
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 1..len - 2 {
+            for j in (i + 1)..len - 1 {
+                let (before, first) = (sequence[i - 1], sequence[i]);
+                let (last, after) = (sequence[j], sequence[j + 1]);
+                let delta = dist(before, last) + dist(first, after)
+                    - dist(before, first)
+                    - dist(last, after);
+                if delta < -0.001 {
+                    sequence[i..=j].reverse();
+                    improved = true;
+                }
+            }
+        }
+    }
+}
+
+/// Output: [reachable, stopCount, (stop, waypointIndex) per stop in visiting order, x1, y1, ...]
+/// with waypoints in world coordinates; `stop` is `stop_key(index)` (e.g. a product id)
+pub fn pack_pick_route(
+    route: &PickRoute,
+    stop_key: impl Fn(usize) -> f32,
+    config: &StoreConfig,
+) -> Vec<f32> {
+    let mut output =
+        Vec::with_capacity(2 + route.order.len() * 2 + route.route.waypoints.len() * 2);
+    output.push(if route.route.reachable { 1.0 } else { 0.0 });
+    output.push(route.order.len() as f32);
+    for (stop, waypoint) in route.order.iter().zip(&route.stop_waypoints) {
+        output.extend_from_slice(&[stop_key(*stop), *waypoint as f32]);
+    }
+    for (x, y) in &route.route.waypoints {
+        let (tx, ty) = config.transform_coords(*x, *y);
+        output.extend_from_slice(&[tx, ty]);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    fn tour_length(sequence: &[usize], dist: &impl Fn(usize, usize) -> f32) -> f32 {
+        sequence.windows(2).map(|pair| dist(pair[0], pair[1])).sum()
+    }
+
+    #[test]
+    fn two_opt_never_lengthens_a_tour() {
+        let mut rng = Rng::new(5);
+        for count in 4..12 {
+            for _ in 0..25 {
+                let points: Vec<(f32, f32)> = (0..count)
+                    .map(|_| (rng.next_f32() * 200.0, rng.next_f32() * 100.0))
+                    .collect();
+                let dist = |a: usize, b: usize| {
+                    (points[a].0 - points[b].0).hypot(points[a].1 - points[b].1)
+                };
+                for mut sequence in [
+                    (0..count).collect::<Vec<_>>(),
+                    nearest_neighbour_order(count, &dist),
+                ] {
+                    let before = tour_length(&sequence, &dist);
+                    two_opt(&mut sequence, &dist);
+
+                    assert!(tour_length(&sequence, &dist) <= before + 1e-3);
+                    assert_eq!((sequence[0], sequence[count - 1]), (0, count - 1));
+                    let mut visited = sequence.clone();
+                    visited.sort();
+                    assert_eq!(visited, (0..count).collect::<Vec<_>>());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use crate::error::SimError;
+use crate::events::SimEvent;
+use crate::simulation::Simulation;
+use crate::snapshot::{SnapshotReader, SnapshotWriter};
+
+/// First bytes of every recording
+const RECORDING_MAGIC: [u8; 4] = *b"TREC";
+
+/// Bump whenever the encoding of an input or event changes
+pub const RECORDING_VERSION: u32 = 1;
+
+/// One call that changed the simulation, with its arguments as the caller passed them
+/// (world coordinates), so replaying it goes through the same conversions
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+    SetConfig(Vec<f32>),
+    SetSeed(u32),
+    SetProducts(Vec<f32>),
+    AddProduct(f32, f32),
+    RemoveProduct(u32),
+    AddRobot {
+        x: f32,
+        y: f32,
+        dest_x: f32,
+        dest_y: f32,
+        orientation: f32,
+        speed: f32,
+        variant: u8,
+    },
+    RemoveRobot(u32),
+    SetRobotDestination(u32, f32, f32),
+    SetRobotPath(u32, Vec<f32>),
+    ClearRobotPath(u32),
+    SetChargers(Vec<f32>),
+    SetBatteryModel([f32; 4]),
+    SetRobotBattery(u32, f32),
+    Step(f32),
+}
+
+fn write_floats(out: &mut SnapshotWriter, values: &[f32]) {
+    out.count(values.len());
+    for value in values {
+        out.f32(*value);
+    }
+}
+
+fn read_floats(input: &mut SnapshotReader) -> Result<Vec<f32>, SimError> {
+    let count = input.count(4)?;
+    (0..count).map(|_| input.f32()).collect()
+}
+
+impl Input {
+    pub fn write(&self, out: &mut SnapshotWriter) {
+        match self {
+            Input::SetConfig(config) => {
+                out.u8(0);
+                write_floats(out, config);
+            }
+            Input::SetSeed(seed) => {
+                out.u8(1);
+                out.u32(*seed);
+            }
+            Input::SetProducts(products) => {
+                out.u8(2);
+                write_floats(out, products);
+            }
+            Input::AddProduct(x, y) => {
+                out.u8(3);
+                out.point((*x, *y));
+            }
+            Input::RemoveProduct(id) => {
+                out.u8(4);
+                out.u32(*id);
+            }
+            Input::AddRobot {
+                x,
+                y,
+                dest_x,
+                dest_y,
+                orientation,
+                speed,
+                variant,
+            } => {
+                out.u8(5);
+                out.point((*x, *y));
+                out.point((*dest_x, *dest_y));
+                out.f32(*orientation);
+                out.f32(*speed);
+                out.u8(*variant);
+            }
+            Input::RemoveRobot(id) => {
+                out.u8(6);
+                out.u32(*id);
+            }
+            Input::SetRobotDestination(id, x, y) => {
+                out.u8(7);
+                out.u32(*id);
+                out.point((*x, *y));
+            }
+            Input::SetRobotPath(id, path) => {
+                out.u8(8);
+                out.u32(*id);
+                write_floats(out, path);
+            }
+            Input::ClearRobotPath(id) => {
+                out.u8(9);
+                out.u32(*id);
+            }
+            Input::SetChargers(chargers) => {
+                out.u8(10);
+                write_floats(out, chargers);
+            }
+            Input::SetBatteryModel(model) => {
+                out.u8(11);
+                for value in model {
+                    out.f32(*value);
+                }
+            }
+            Input::SetRobotBattery(id, level) => {
+                out.u8(12);
+                out.u32(*id);
+                out.f32(*level);
+            }
+            Input::Step(delta_ms) => {
+                out.u8(13);
+                out.f32(*delta_ms);
+            }
+        }
+    }
+
+    pub fn read(input: &mut SnapshotReader) -> Result<Self, SimError> {
+        Ok(match input.u8()? {
+            0 => Input::SetConfig(read_floats(input)?),
+            1 => Input::SetSeed(input.u32()?),
+            2 => Input::SetProducts(read_floats(input)?),
+            3 => {
+                let (x, y) = input.point()?;
+                Input::AddProduct(x, y)
+            }
+            4 => Input::RemoveProduct(input.u32()?),
+            5 => {
+                let (x, y) = input.point()?;
+                let (dest_x, dest_y) = input.point()?;
+                Input::AddRobot {
+                    x,
+                    y,
+                    dest_x,
+                    dest_y,
+                    orientation: input.f32()?,
+                    speed: input.f32()?,
+                    variant: input.u8()?,
+                }
+            }
+            6 => Input::RemoveRobot(input.u32()?),
+            7 => {
+                let id = input.u32()?;
+                let (x, y) = input.point()?;
+                Input::SetRobotDestination(id, x, y)
+            }
+            8 => Input::SetRobotPath(input.u32()?, read_floats(input)?),
+            9 => Input::ClearRobotPath(input.u32()?),
+            10 => Input::SetChargers(read_floats(input)?),
+            11 => Input::SetBatteryModel([input.f32()?, input.f32()?, input.f32()?, input.f32()?]),
+            12 => Input::SetRobotBattery(input.u32()?, input.f32()?),
+            13 => Input::Step(input.f32()?),
+            _ => return Err(SimError::InvalidSnapshot("invalid recorded input")),
+        })
+    }
+
+    /// Repeat the call on `sim`. Only calls that succeeded (returned `Ok` or `true`) are
+    /// recorded, so a failure here simply means the replay has diverged, which the event
+    /// comparison reports.
+    pub fn apply(&self, sim: &mut Simulation) {
+        match self {
+            Input::SetConfig(config) => {
+                let _ = sim.set_config(config);
+            }
+            Input::SetSeed(seed) => sim.set_seed(*seed),
+            Input::SetProducts(products) => sim.set_products(products),
+            Input::AddProduct(x, y) => {
+                sim.add_product(*x, *y);
+            }
+            Input::RemoveProduct(id) => {
+                sim.remove_product(*id);
+            }
+            Input::AddRobot {
+                x,
+                y,
+                dest_x,
+                dest_y,
+                orientation,
+                speed,
+                variant,
+            } => {
+                sim.add_robot(*x, *y, *dest_x, *dest_y, *orientation, *speed, *variant);
+            }
+            Input::RemoveRobot(id) => {
+                sim.remove_robot(*id);
+            }
+            Input::SetRobotDestination(id, x, y) => {
+                sim.set_robot_destination(*id, *x, *y);
+            }
+            Input::SetRobotPath(id, path) => {
+                let _ = sim.set_robot_path(*id, path);
+            }
+            Input::ClearRobotPath(id) => {
+                sim.clear_robot_path(*id);
+            }
+            Input::SetChargers(chargers) => {
+                let _ = sim.set_chargers(chargers);
+            }
+            Input::SetBatteryModel([drain, idle, charge, low]) => {
+                sim.set_battery_model(*drain, *idle, *charge, *low)
+            }
+            Input::SetRobotBattery(id, level) => {
+                sim.set_robot_battery(*id, *level);
+            }
+            Input::Step(delta_ms) => sim.step(*delta_ms),
+        }
+    }
+}
+
+/// Everything needed to re-run a session: the state it started from (seed included), every
+/// input since, and the events those inputs produced
+#[derive(Clone, Debug, Default)]
+pub struct Recording {
+    pub start: Vec<u8>,
+    pub inputs: Vec<Input>,
+    pub events: Vec<SimEvent>,
+}
+
+/// Outcome of re-running a recording and comparing its event logs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReplayReport {
+    pub recorded: usize,
+    pub replayed: usize,
+    /// Index of the first event that differs, including one log running out before the other
+    pub first_mismatch: Option<usize>,
+}
+
+impl ReplayReport {
+    pub fn matches(&self) -> bool {
+        self.first_mismatch.is_none()
+    }
+
+    /// Output: [matches, recordedCount, replayedCount, firstMismatch], firstMismatch -1 when
+    /// the logs agree
+    pub fn to_buffer(&self) -> Vec<f32> {
+        vec![
+            if self.matches() { 1.0 } else { 0.0 },
+            self.recorded as f32,
+            self.replayed as f32,
+            self.first_mismatch.map_or(-1.0, |index| index as f32),
+        ]
+    }
+}
+
+impl Recording {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = SnapshotWriter::with_header(RECORDING_MAGIC, RECORDING_VERSION);
+        out.bytes(&self.start);
+        out.count(self.inputs.len());
+        for input in &self.inputs {
+            input.write(&mut out);
+        }
+        out.count(self.events.len());
+        for event in &self.events {
+            event.write(&mut out);
+        }
+        out.finish()
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, SimError> {
+        let mut input = SnapshotReader::with_header(bytes, RECORDING_MAGIC, RECORDING_VERSION)?;
+        let start = input.bytes()?.to_vec();
+        let input_count = input.count(1)?;
+        let inputs = (0..input_count)
+            .map(|_| Input::read(&mut input))
+            .collect::<Result<Vec<_>, _>>()?;
+        let event_count = input.count(SimEvent::SNAPSHOT_SIZE)?;
+        let events = (0..event_count)
+            .map(|_| SimEvent::read(&mut input))
+            .collect::<Result<Vec<_>, _>>()?;
+        input.finish()?;
+        Ok(Recording {
+            start,
+            inputs,
+            events,
+        })
+    }
+
+    /// Restore the starting state, apply every input again and compare the events
+    pub fn replay(&self) -> Result<ReplayReport, SimError> {
+        let mut sim = Simulation::read_snapshot(&self.start)?;
+        sim.capture_events();
+        for input in &self.inputs {
+            input.apply(&mut sim);
+        }
+        let replayed = sim.captured_events();
+        let first_mismatch = self
+            .events
+            .iter()
+            .zip(&replayed)
+            .position(|(recorded, replayed)| recorded != replayed)
+            .or_else(|| {
+                (self.events.len() != replayed.len()).then(|| self.events.len().min(replayed.len()))
+            });
+        Ok(ReplayReport {
+            recorded: self.events.len(),
+            replayed: replayed.len(),
+            first_mismatch,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::tests::busy_simulation;
+
+    /// Recording of a run with a call of most kinds along the way
+    fn recorded_run() -> Recording {
+        let mut sim = busy_simulation(6);
+        for _ in 0..100 {
+            sim.step(50.0);
+        }
+        sim.start_recording();
+        for tick in 0..600 {
+            match tick {
+                50 => assert!(sim.set_robot_destination(0, -85.0, 40.0)),
+                120 => {
+                    sim.add_product(-25.0, 40.0);
+                }
+                200 => assert!(sim.remove_product(1)),
+                260 => assert!(sim.clear_robot_path(1)),
+                300 => sim.set_seed(77),
+                400 => assert!(sim.remove_robot(4)),
+                _ => {}
+            }
+            sim.step(50.0);
+        }
+        let recording = sim.finish_recording().unwrap_or_default();
+        Recording::decode(&recording).unwrap()
+    }
+
+    #[test]
+    fn replay_matches_the_recorded_run() {
+        let recording = recorded_run();
+        let report = recording.replay().unwrap();
+
+        assert!(report.recorded > 0);
+        assert!(report.matches(), "{report:?}");
+        assert_eq!(report.recorded, report.replayed);
+    }
+
+    #[test]
+    fn replay_spots_a_changed_run() {
+        let mut recording = recorded_run();
+        recording.events[3].x += 1.0;
+        assert_eq!(recording.replay().unwrap().first_mismatch, Some(3));
+
+        let mut recording = recorded_run();
+        let seed = recording
+            .inputs
+            .iter()
+            .position(|input| matches!(input, Input::SetSeed(_)))
+            .unwrap();
+        recording.inputs[seed] = Input::SetSeed(78);
+        assert!(!recording.replay().unwrap().matches());
+    }
+}
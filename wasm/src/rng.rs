@@ -0,0 +1,40 @@
+/// Seed used until `set_seed` is called, so a fresh simulation is reproducible by default
+pub const DEFAULT_SEED: u64 = 0x5EED_0000_7A11;
+
+/// Small deterministic PRNG (SplitMix64). Fast, seedable and identical on every platform,
+/// which keeps robot decisions reproducible across runs and between wasm and native builds.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    /// Current position in the sequence; `Rng::new(rng.state())` continues exactly where
+    /// `rng` is
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform float in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Rng::new(DEFAULT_SEED)
+    }
+}
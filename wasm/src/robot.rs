@@ -1,6 +1,10 @@
 use crate::constants::*;
 use crate::geometry::*;
 use crate::grid::is_in_row_walkway;
+use crate::kinematics::{Drive, integrate_motion};
+use crate::layout::ROBOT_STRIDE;
+use crate::rng::Rng;
+use crate::spatial::ProductIndex;
 
 pub fn find_nearest_valid_position(x: f32, y: f32, config: &StoreConfig) -> (f32, f32) {
     let mut nearest_row_x = config.get_row_center(0);
@@ -23,37 +27,29 @@ pub fn find_nearest_valid_position(x: f32, y: f32, config: &StoreConfig) -> (f32
     (nearest_row_x, clamped_y)
 }
 
-pub fn get_valid_destination(config: &StoreConfig) -> (f32, f32) {
+pub fn get_valid_destination(config: &StoreConfig, rng: &mut Rng) -> (f32, f32) {
     for _ in 0..50 {
-        if random_f32() > 0.2 {
-            let row_num = (random_f32() * config.row_count as f32).floor() as i32;
+        if rng.next_f32() > 0.2 {
+            let row_num = (rng.next_f32() * config.row_count as f32).floor() as i32;
             let row_x = config.get_row_center(row_num);
-            let y = random_f32() * (config.store_height - 30.0) - (config.store_height - 30.0) / 2.0;
+            let y = rng.next_f32() * (config.store_height - 30.0) - (config.store_height - 30.0) / 2.0;
             return (row_x, y);
         } else {
-            let x = random_f32() * config.store_width - config.store_width / 2.0;
-            let y = random_f32() * config.store_height - config.store_height / 2.0;
+            let x = rng.next_f32() * config.store_width - config.store_width / 2.0;
+            let y = rng.next_f32() * config.store_height - config.store_height / 2.0;
             if is_in_row_walkway(x, y, config) {
                 return (x, y);
             }
         }
     }
 
-    let row_num = (random_f32() * config.row_count as f32).floor() as i32;
+    let row_num = (rng.next_f32() * config.row_count as f32).floor() as i32;
     let row_x = config.get_row_center(row_num);
     (row_x, 0.0)
 }
 
-pub fn check_product_collision(x: f32, y: f32, products: &[f32]) -> Option<(f32, f32)> {
-    for chunk in products.chunks_exact(2) {
-        let dx = x - chunk[0];
-        let dy = y - chunk[1];
-        let distance = (dx * dx + dy * dy).sqrt();
-        if distance < ROBOT_RADIUS + PRODUCT_RADIUS + COLLISION_BUFFER {
-            return Some((chunk[0], chunk[1]));
-        }
-    }
-    None
+pub fn check_product_collision(x: f32, y: f32, products: &ProductIndex) -> Option<(f32, f32)> {
+    products.find_within(x, y, PRODUCT_CLEARANCE)
 }
 
 pub fn check_product_collision_along_segment(
@@ -61,32 +57,24 @@ pub fn check_product_collision_along_segment(
     y1: f32,
     x2: f32,
     y2: f32,
-    products: &[f32],
+    products: &ProductIndex,
 ) -> Option<(f32, f32)> {
-    let radius = ROBOT_RADIUS + PRODUCT_RADIUS + COLLISION_BUFFER;
-    let seg_dx = x2 - x1;
-    let seg_dy = y2 - y1;
-    let seg_len_sq = seg_dx * seg_dx + seg_dy * seg_dy;
-    for chunk in products.chunks_exact(2) {
-        let px = chunk[0];
-        let py = chunk[1];
-        let t = if seg_len_sq > 0.0001 {
-            ((px - x1) * seg_dx + (py - y1) * seg_dy) / seg_len_sq
-        } else {
-            0.0
-        };
-        let clamped_t = t.clamp(0.0, 1.0);
-        let closest_x = x1 + seg_dx * clamped_t;
-        let closest_y = y1 + seg_dy * clamped_t;
-        let dx = closest_x - px;
-        let dy = closest_y - py;
-        if dx * dx + dy * dy < radius * radius {
-            return Some((px, py));
-        }
-    }
-    None
+    products.find_along_segment((x1, y1), (x2, y2), PRODUCT_CLEARANCE)
 }
 
+/// What happened to a robot during one tick, beyond its new state, for the simulation's
+/// event log
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TickOutcome {
+    /// It had not moved for `STUCK_TIMEOUT` and was sent somewhere new
+    pub stuck: bool,
+    /// Position of the product it bumped into
+    pub collision: Option<(f32, f32)>,
+    /// Destination or path waypoint it reached
+    pub waypoint: Option<(f32, f32)>,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn update_single_robot(
     x: f32,
     y: f32,
@@ -95,17 +83,22 @@ pub fn update_single_robot(
     orientation: f32,
     speed: f32,
     last_move_ms: f32,
-    products: &[f32],
+    battery: f32,
+    products: &ProductIndex,
     config: &StoreConfig,
     delta: f32,
-) -> [f32; 7] {
+    rng: &mut Rng,
+    mut drive: Option<&mut Drive>,
+    outcome: &mut TickOutcome,
+) -> [f32; ROBOT_STRIDE] {
     let mut dest_x = dest_x;
     let mut dest_y = dest_y;
     let mut orientation = orientation;
     let mut last_move_ms = last_move_ms;
 
     if last_move_ms > STUCK_TIMEOUT {
-        let (nx, ny) = get_valid_destination(config);
+        outcome.stuck = true;
+        let (nx, ny) = get_valid_destination(config, rng);
         dest_x = nx;
         dest_y = ny;
         last_move_ms = 0.0;
@@ -116,7 +109,8 @@ pub fn update_single_robot(
     let mut distance = (dx * dx + dy * dy).sqrt();
 
     if distance < 2.0 {
-        let (nx, ny) = get_valid_destination(config);
+        outcome.waypoint = Some((dest_x, dest_y));
+        let (nx, ny) = get_valid_destination(config, rng);
         dest_x = nx;
         dest_y = ny;
         dx = dest_x - x;
@@ -124,16 +118,29 @@ pub fn update_single_robot(
         distance = (dx * dx + dy * dy).sqrt();
     }
 
-    let move_amount = speed * (delta / 1000.0);
-    let move_x = if distance > 0.0001 {
-        dx / distance * move_amount
-    } else {
-        0.0
-    };
-    let move_y = if distance > 0.0001 {
-        dy / distance * move_amount
+    let mut driven_heading = None;
+    let (move_x, move_y) = if let Some(drive) = drive.as_deref_mut() {
+        // Headings here follow the atan2(dx, dy) convention used for `orientation` below
+        let step = integrate_motion(
+            orientation,
+            drive.velocity,
+            dx.atan2(dy),
+            distance,
+            speed,
+            &drive.limits,
+            delta,
+        );
+        drive.velocity = step.velocity;
+        driven_heading = Some(step.heading);
+        (
+            step.heading.sin() * step.travel,
+            step.heading.cos() * step.travel,
+        )
+    } else if distance > 0.0001 {
+        let move_amount = speed * (delta / 1000.0);
+        (dx / distance * move_amount, dy / distance * move_amount)
     } else {
-        0.0
+        (0.0, 0.0)
     };
 
     let mut new_x = x + move_x;
@@ -141,16 +148,23 @@ pub fn update_single_robot(
 
     if !is_in_row_walkway(x, y, config) {
         let (valid_x, valid_y) = find_nearest_valid_position(x, y, config);
-        let (nx, ny) = get_valid_destination(config);
-        return [valid_x, valid_y, nx, ny, orientation, speed, 0.0];
+        let (nx, ny) = get_valid_destination(config, rng);
+        if let Some(drive) = drive {
+            drive.velocity = 0.0;
+        }
+        return [valid_x, valid_y, nx, ny, orientation, speed, 0.0, battery];
     }
 
     if !is_in_row_walkway(new_x, new_y, config) {
-        let (nx, ny) = get_valid_destination(config);
-        return [x, y, nx, ny, orientation, speed, 0.0];
+        let (nx, ny) = get_valid_destination(config, rng);
+        if let Some(drive) = drive {
+            drive.velocity = 0.0;
+        }
+        return [x, y, nx, ny, orientation, speed, 0.0, battery];
     }
 
     if let Some((px, py)) = check_product_collision(new_x, new_y, products) {
+        outcome.collision = Some((px, py));
         let dxp = x - px;
         let dyp = y - py;
         let dist = (dxp * dxp + dyp * dyp).sqrt().max(0.0001);
@@ -186,7 +200,7 @@ pub fn update_single_robot(
         );
 
         if !is_in_row_walkway(dest_x, dest_y, config) {
-            let (nx, ny) = get_valid_destination(config);
+            let (nx, ny) = get_valid_destination(config, rng);
             dest_x = nx;
             dest_y = ny;
         }
@@ -198,15 +212,22 @@ pub fn update_single_robot(
         if !is_in_row_walkway(new_x, new_y, config) {
             new_x = x;
             new_y = y;
-            let (nx, ny) = get_valid_destination(config);
+            let (nx, ny) = get_valid_destination(config, rng);
             dest_x = nx;
             dest_y = ny;
         }
     }
 
-    let target_orientation = (new_x - x).atan2(new_y - y);
-    let orientation_diff = shortest_angle_diff(target_orientation, orientation);
-    orientation += orientation_diff * 0.25;
+    orientation = match driven_heading {
+        Some(heading) => heading,
+        None => {
+            // Ease towards the travel direction, closing 25% of the gap per default-length tick
+            let target_orientation = (new_x - x).atan2(new_y - y);
+            let orientation_diff = shortest_angle_diff(target_orientation, orientation);
+            let blend = 1.0 - 0.75_f32.powf(delta / UPDATE_INTERVAL_DEFAULT);
+            orientation + orientation_diff * blend
+        }
+    };
 
     let has_moved = (new_x - x).abs() > 0.01 || (new_y - y).abs() > 0.01;
     last_move_ms = if has_moved { 0.0 } else { last_move_ms + delta };
@@ -219,9 +240,68 @@ pub fn update_single_robot(
         orientation,
         speed,
         last_move_ms,
+        battery,
     ]
 }
 
+/// Push overlapping robots apart after a tick so they never drive through each other.
+/// Operates in place on `update_single_robot` outputs; each overlapping pair is split evenly,
+/// falling back to moving only the robot whose pushed position stays in a walkway.
+/// Runs a fixed number of pair sweeps in input order so the result is deterministic and the
+/// cost stays bounded at O(iterations * n²). Returns the number of overlaps resolved.
+pub fn separate_robots(robots: &mut [[f32; ROBOT_STRIDE]], config: &StoreConfig) -> usize {
+    let mut resolved = 0;
+    for _ in 0..SEPARATION_ITERATIONS {
+        let mut any_overlap = false;
+        for i in 0..robots.len() {
+            for j in (i + 1)..robots.len() {
+                let dx = robots[j][0] - robots[i][0];
+                let dy = robots[j][1] - robots[i][1];
+                let dist_sq = dx * dx + dy * dy;
+                if dist_sq >= ROBOT_SEPARATION * ROBOT_SEPARATION {
+                    continue;
+                }
+                any_overlap = true;
+                resolved += 1;
+
+                let dist = dist_sq.sqrt();
+                // Robots on the exact same spot split along x, lower index to the left
+                let (normal_x, normal_y) = if dist > 0.0001 {
+                    (dx / dist, dy / dist)
+                } else {
+                    (1.0, 0.0)
+                };
+                let overlap = ROBOT_SEPARATION - dist;
+
+                let (ix, iy) = (robots[i][0], robots[i][1]);
+                let (jx, jy) = (robots[j][0], robots[j][1]);
+                let half = overlap / 2.0;
+                let i_split = (ix - normal_x * half, iy - normal_y * half);
+                let j_split = (jx + normal_x * half, jy + normal_y * half);
+
+                if is_in_row_walkway(i_split.0, i_split.1, config)
+                    && is_in_row_walkway(j_split.0, j_split.1, config)
+                {
+                    (robots[i][0], robots[i][1]) = i_split;
+                    (robots[j][0], robots[j][1]) = j_split;
+                } else {
+                    let j_full = (jx + normal_x * overlap, jy + normal_y * overlap);
+                    let i_full = (ix - normal_x * overlap, iy - normal_y * overlap);
+                    if is_in_row_walkway(j_full.0, j_full.1, config) {
+                        (robots[j][0], robots[j][1]) = j_full;
+                    } else if is_in_row_walkway(i_full.0, i_full.1, config) {
+                        (robots[i][0], robots[i][1]) = i_full;
+                    }
+                }
+            }
+        }
+        if !any_overlap {
+            break;
+        }
+    }
+    resolved
+}
+
 pub fn move_to_waypoint(
   x: f32,
   y: f32,
@@ -250,6 +330,49 @@ pub fn move_to_waypoint(
   [new_x, new_y, direction]
 }
 
+/// Kinematic counterpart of `move_to_waypoint`: the robot turns and accelerates within the
+/// drive's limits instead of snapping to the waypoint direction at full speed.
+/// Output: [newX, newY, orientation, velocity]
+#[allow(clippy::too_many_arguments)]
+pub fn move_to_waypoint_kinematic(
+    x: f32,
+    y: f32,
+    orientation: f32,
+    speed: f32,
+    waypoint_x: f32,
+    waypoint_y: f32,
+    delta_ms: f32,
+    drive: &mut Drive,
+) -> [f32; 4] {
+    let dx = waypoint_x - x;
+    let dy = waypoint_y - y;
+    let distance = (dx * dx + dy * dy).sqrt();
+
+    if distance < 0.01 {
+        drive.velocity = 0.0;
+        return [x, y, orientation, 0.0];
+    }
+
+    let step = integrate_motion(
+        orientation,
+        drive.velocity,
+        dy.atan2(dx),
+        distance,
+        speed,
+        &drive.limits,
+        delta_ms,
+    );
+    drive.velocity = step.velocity;
+
+    [
+        x + step.heading.cos() * step.travel,
+        y + step.heading.sin() * step.travel,
+        step.heading,
+        step.velocity,
+    ]
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn move_to_waypoint_with_collision(
     x: f32,
     y: f32,
@@ -258,7 +381,7 @@ pub fn move_to_waypoint_with_collision(
     waypoint_x: f32,
     waypoint_y: f32,
     delta_ms: f32,
-    products: &[f32],
+    products: &ProductIndex,
     config: &StoreConfig,
 ) -> [f32; 3] {
     let delta_seconds = delta_ms / 1000.0;
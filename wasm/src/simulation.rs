@@ -0,0 +1,1493 @@
+use crate::allocation::{allocate_tasks, pack_task_assignments, unpack_points};
+use crate::battery::{BatteryModel, Chargers};
+use crate::constants::*;
+use crate::cooperative::*;
+use crate::error::*;
+use crate::events::{EVENT_STRIDE, EventKind, EventQueue, SimEvent};
+use crate::follower::{FollowState, Polyline, pure_pursuit_step};
+use crate::grid::{NavGrid, build_nav_grid};
+use crate::heatmap::TrafficHeatmap;
+use crate::kinematics::{Drive, KinematicLimits, RobotVariant};
+use crate::layout::{CONFIG_LEN, HEADER_LEN, ROBOT_LAYOUT, ROBOT_STRIDE};
+use crate::metrics::{Activity, FleetStats, RobotStats};
+use crate::pathfinding::*;
+use crate::picking::{pack_pick_route, pickup_pose, plan_pick_route};
+use crate::replay::{Input, Recording};
+use crate::rng::Rng;
+use crate::robot::{TickOutcome, get_valid_destination, separate_robots, update_single_robot};
+use crate::snapshot::{SnapshotReader, SnapshotWriter};
+use crate::spatial::ProductIndex;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// Path a robot is following with pure pursuit instead of wandering
+#[derive(Clone, Debug)]
+struct Route {
+    path: Polyline,
+    progress: f32,
+    remaining: f32,
+    done: bool,
+}
+
+/// What a robot is doing about its battery
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Power {
+    /// Wandering or following its path
+    Working,
+    /// Driving to the charger it has claimed
+    ToCharger(usize),
+    /// Standing still until a charger frees up
+    Queued,
+    Charging(usize),
+}
+
+impl Power {
+    /// Number used for the phase in snapshots and `PhaseChanged` events
+    fn code(self) -> u8 {
+        match self {
+            Power::Working => 0,
+            Power::ToCharger(_) => 1,
+            Power::Queued => 2,
+            Power::Charging(_) => 3,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct SimRobot {
+    id: u32,
+    x: f32,
+    y: f32,
+    dest_x: f32,
+    dest_y: f32,
+    orientation: f32,
+    speed: f32,
+    last_move_ms: f32,
+    drive: Drive,
+    route: Option<Route>,
+    battery: f32,
+    power: Power,
+    stats: RobotStats,
+    /// When the path from `set_robot_path` was assigned, until the robot arrives
+    task_started_ms: Option<f64>,
+}
+
+impl SimRobot {
+    /// Store-frame state in the robot buffer layout
+    fn state(&self) -> [f32; ROBOT_STRIDE] {
+        [
+            self.x,
+            self.y,
+            self.dest_x,
+            self.dest_y,
+            self.orientation,
+            self.speed,
+            self.last_move_ms,
+            self.battery,
+        ]
+    }
+
+    /// Charging, waiting for a charger, or out of charge; robots already on their way to a
+    /// charger drive on reserve so they cannot strand themselves holding it
+    fn is_parked(&self) -> bool {
+        match self.power {
+            Power::Queued | Power::Charging(_) => true,
+            Power::ToCharger(_) => false,
+            Power::Working => self.battery <= 0.0,
+        }
+    }
+
+    /// Smallest encoded robot: no route
+    const SNAPSHOT_MIN_SIZE: usize =
+        4 + ROBOT_STRIDE * 4 + 4 * 4 + 1 + 4 + RobotStats::SNAPSHOT_SIZE + 8 + 1;
+
+    fn write(&self, out: &mut SnapshotWriter) {
+        out.u32(self.id);
+        for value in self.state() {
+            out.f32(value);
+        }
+        let limits = self.drive.limits;
+        out.f32(limits.max_acceleration);
+        out.f32(limits.max_angular_velocity);
+        out.f32(limits.turn_in_place_threshold.unwrap_or(f32::NAN));
+        out.f32(self.drive.velocity);
+        let charger = match self.power {
+            Power::ToCharger(charger) | Power::Charging(charger) => charger,
+            Power::Working | Power::Queued => 0,
+        };
+        out.u8(self.power.code());
+        out.count(charger);
+        self.stats.write(out);
+        out.f64(self.task_started_ms.unwrap_or(f64::NAN));
+        match &self.route {
+            Some(route) => {
+                out.bool(true);
+                out.count(route.path.points().len());
+                for point in route.path.points() {
+                    out.point(*point);
+                }
+                out.f32(route.progress);
+                out.f32(route.remaining);
+                out.bool(route.done);
+            }
+            None => out.bool(false),
+        }
+    }
+
+    fn read(input: &mut SnapshotReader, charger_count: usize) -> Result<SimRobot, SimError> {
+        let id = input.u32()?;
+        let mut state = [0.0; ROBOT_STRIDE];
+        for value in &mut state {
+            *value = input.f32()?;
+        }
+        let [
+            x,
+            y,
+            dest_x,
+            dest_y,
+            orientation,
+            speed,
+            last_move_ms,
+            battery,
+        ] = state;
+        let limits = KinematicLimits {
+            max_acceleration: input.f32()?,
+            max_angular_velocity: input.f32()?,
+            turn_in_place_threshold: Some(input.f32()?).filter(|threshold| !threshold.is_nan()),
+        };
+        let drive = Drive {
+            limits,
+            velocity: input.f32()?,
+        };
+        let power = input.u8()?;
+        let charger = input.u32()? as usize;
+        if matches!(power, 1 | 3) && charger >= charger_count {
+            return Err(SimError::InvalidSnapshot("robot holds an unknown charger"));
+        }
+        let power = match power {
+            0 => Power::Working,
+            1 => Power::ToCharger(charger),
+            2 => Power::Queued,
+            3 => Power::Charging(charger),
+            _ => return Err(SimError::InvalidSnapshot("invalid robot power state")),
+        };
+        let stats = RobotStats::read(input)?;
+        let task_started_ms = Some(input.f64()?).filter(|started| !started.is_nan());
+        let route = if input.bool()? {
+            let count = input.count(8)?;
+            let points = (0..count)
+                .map(|_| input.point())
+                .collect::<Result<Vec<_>, _>>()?;
+            Some(Route {
+                path: Polyline::new(points),
+                progress: input.f32()?,
+                remaining: input.f32()?,
+                done: input.bool()?,
+            })
+        } else {
+            None
+        };
+        Ok(SimRobot {
+            id,
+            x,
+            y,
+            dest_x,
+            dest_y,
+            orientation,
+            speed,
+            last_move_ms,
+            drive,
+            route,
+            battery,
+            power,
+            stats,
+            task_started_ms,
+        })
+    }
+
+    /// Follow `points` (store frame) with pure pursuit, stopping at the last one
+    fn follow(&mut self, points: Vec<(f32, f32)>) {
+        let path = Polyline::new(points);
+        (self.dest_x, self.dest_y) = path.point_at(path.length());
+        self.last_move_ms = 0.0;
+        self.route = Some(Route {
+            remaining: path.length(),
+            path,
+            progress: 0.0,
+            done: false,
+        });
+    }
+}
+
+/// Stateful store simulation that keeps the parsed config, nav grid, products and robots
+/// in wasm memory between ticks.
+/// Everything is stored in the store frame (rows run along Y); positions are transformed
+/// on the way in and out so callers keep working in world coordinates.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct Simulation {
+    config: StoreConfig,
+    nav_grid: NavGrid,
+    search: PathSearch,
+    products: ProductIndex,
+    /// Set when products were added or removed since the nav grid was last built
+    nav_grid_dirty: bool,
+    robots: Vec<SimRobot>,
+    next_robot_id: u32,
+    rng: Rng,
+    battery: BatteryModel,
+    chargers: Chargers,
+    /// Simulated time advanced by `step`, used to stamp events
+    elapsed_ms: f64,
+    /// `elapsed_ms` when stats were last reset
+    stats_since_ms: f64,
+    /// Stats of robots removed since the last reset, so fleet totals keep their work
+    retired_stats: RobotStats,
+    heatmap: TrafficHeatmap,
+    /// Events not yet collected with `drain_events`
+    events: EventQueue,
+    /// Set between `start_recording` and `finish_recording`
+    recording: Option<Recording>,
+    /// Store-frame tick results, kept between ticks so `step` does not allocate
+    step_results: Vec<[f32; ROBOT_STRIDE]>,
+    step_outcomes: Vec<TickOutcome>,
+    /// Store-frame positions before the separation pass, to spot robots it pushed apart
+    step_planned: Vec<(f32, f32)>,
+    /// World-frame robot states in the robot buffer layout, exposed to JS by pointer
+    robot_view: Vec<f32>,
+    /// World-frame product positions indexed by id (NaN once removed), exposed to JS by pointer
+    product_view: Vec<f32>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Simulation {
+    /// Config format: [version, storeWidth, storeHeight, rowCount, rowSpacing, rowThickness, startOffset, walkwayWidth, crossRowBuffer, outerWalkwayOffset, orientation]
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(config: &[f32]) -> Result<Simulation, ExportError> {
+        Ok(Simulation::with_config(StoreConfig::parse(config)?))
+    }
+
+    /// Replace the store layout, rebuilding the nav grid and re-projecting robots and products
+    pub fn set_config(&mut self, config: &[f32]) -> Result<(), ExportError> {
+        let previous = self.config;
+        let next = StoreConfig::parse(config)?;
+
+        // Both transforms are their own inverse, so applying the old one restores world coords
+        self.products = self.products.transformed(&previous, &next);
+        for robot in &mut self.robots {
+            let (x, y) = previous.transform_coords(robot.x, robot.y);
+            let (dest_x, dest_y) = previous.transform_coords(robot.dest_x, robot.dest_y);
+            let orientation = previous.transform_orientation(robot.orientation);
+            (robot.x, robot.y) = next.transform_coords(x, y);
+            (robot.dest_x, robot.dest_y) = next.transform_coords(dest_x, dest_y);
+            robot.orientation = next.transform_orientation(orientation);
+        }
+        for position in self.chargers.positions_mut() {
+            let (x, y) = previous.transform_coords(position.0, position.1);
+            *position = next.transform_coords(x, y);
+        }
+
+        self.config = next;
+        self.rebuild_nav_grid();
+        // Cells no longer cover the same floor space
+        self.heatmap = TrafficHeatmap::new(&self.nav_grid);
+        self.sync_robot_view();
+        self.sync_product_view();
+        self.record(|| Input::SetConfig(config.to_vec()));
+        Ok(())
+    }
+
+    /// Reseed the simulation's random source; the same seed and inputs give identical runs
+    pub fn set_seed(&mut self, seed: u32) {
+        self.rng = Rng::new(seed as u64);
+        self.record(|| Input::SetSeed(seed));
+    }
+
+    /// Products format: [x1, y1, x2, y2, ...] in world coordinates
+    /// Product ids are their position in this buffer
+    pub fn set_products(&mut self, products: &[f32]) {
+        self.products =
+            ProductIndex::build(&self.config.transform_products(products), &self.config);
+        self.rebuild_nav_grid();
+        self.sync_product_view();
+        self.record(|| Input::SetProducts(products.to_vec()));
+    }
+
+    /// Add a single product and return its id
+    pub fn add_product(&mut self, x: f32, y: f32) -> u32 {
+        let (wx, wy) = (x, y);
+        let (x, y) = self.config.transform_coords(x, y);
+        self.nav_grid_dirty = true;
+        self.product_view.extend_from_slice(&[wx, wy]);
+        self.record(|| Input::AddProduct(wx, wy));
+        self.products.insert(x, y)
+    }
+
+    /// Remove a product, e.g. once it has been picked up. Returns false if the id is unknown.
+    pub fn remove_product(&mut self, id: u32) -> bool {
+        let removed = self.products.remove(id);
+        if removed {
+            let offset = id as usize * 2;
+            self.product_view[offset..offset + 2].fill(f32::NAN);
+        }
+        self.nav_grid_dirty |= removed;
+        if removed {
+            self.record(|| Input::RemoveProduct(id));
+        }
+        removed
+    }
+
+    pub fn product_count(&self) -> usize {
+        self.products.len()
+    }
+
+    /// Add a robot and return its id
+    /// Variant: 0 walking, 1 tracked, 2 dome; selects the acceleration and turn-rate limits
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_robot(
+        &mut self,
+        x: f32,
+        y: f32,
+        dest_x: f32,
+        dest_y: f32,
+        orientation: f32,
+        speed: f32,
+        variant: u8,
+    ) -> u32 {
+        self.record(|| Input::AddRobot {
+            x,
+            y,
+            dest_x,
+            dest_y,
+            orientation,
+            speed,
+            variant,
+        });
+        let id = self.next_robot_id;
+        self.next_robot_id += 1;
+
+        let (x, y) = self.config.transform_coords(x, y);
+        let (dest_x, dest_y) = self.config.transform_coords(dest_x, dest_y);
+        self.robots.push(SimRobot {
+            id,
+            x,
+            y,
+            dest_x,
+            dest_y,
+            orientation: self.config.transform_orientation(orientation),
+            speed,
+            last_move_ms: 0.0,
+            drive: Drive::new(RobotVariant::from_index(variant)),
+            route: None,
+            battery: 1.0,
+            power: Power::Working,
+            stats: RobotStats::default(),
+            task_started_ms: None,
+        });
+        self.sync_robot_view();
+        id
+    }
+
+    /// Remove a robot, returning false if the id is unknown
+    pub fn remove_robot(&mut self, id: u32) -> bool {
+        let before = self.robots.len();
+        if let Some(index) = self.robots.iter().position(|robot| robot.id == id) {
+            self.abandon_task(index);
+            self.retired_stats.add(&self.robots[index].stats);
+        }
+        self.robots.retain(|robot| robot.id != id);
+        if self.chargers.release(id).is_some() {
+            self.serve_charger_queue();
+        }
+        self.sync_robot_view();
+        let removed = self.robots.len() != before;
+        if removed {
+            self.record(|| Input::RemoveRobot(id));
+        }
+        removed
+    }
+
+    pub fn robot_count(&self) -> usize {
+        self.robots.len()
+    }
+
+    /// Ids of all robots in the same order as `robot_states`
+    pub fn robot_ids(&self) -> Vec<u32> {
+        self.robots.iter().map(|robot| robot.id).collect()
+    }
+
+    /// Point a robot at a new destination, returning false if the id is unknown
+    pub fn set_robot_destination(&mut self, id: u32, dest_x: f32, dest_y: f32) -> bool {
+        let (wx, wy) = (dest_x, dest_y);
+        let (dest_x, dest_y) = self.config.transform_coords(dest_x, dest_y);
+        match self.robots.iter_mut().find(|robot| robot.id == id) {
+            Some(robot) => {
+                robot.dest_x = dest_x;
+                robot.dest_y = dest_y;
+                robot.last_move_ms = 0.0;
+                self.sync_robot_view();
+                self.record(|| Input::SetRobotDestination(id, wx, wy));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Have a robot follow a path (packed [x1, y1, x2, y2, ...], e.g. the waypoints from
+    /// `compute_path`) with pure pursuit instead of wandering. The robot stops at the end of
+    /// the path until it is given a new path or the path is cleared.
+    /// Robots that are driving to, waiting for or sitting on a charger keep their charger
+    /// route and reject the path until they are back at work.
+    pub fn set_robot_path(&mut self, id: u32, path: &[f32]) -> Result<(), ExportError> {
+        require_len("path", path, 2)?;
+        require_stride("path", path, 2)?;
+        let points: Vec<(f32, f32)> = path
+            .chunks_exact(2)
+            .map(|point| self.config.transform_coords(point[0], point[1]))
+            .collect();
+        let index = self
+            .robots
+            .iter()
+            .position(|robot| robot.id == id)
+            .ok_or(SimError::UnknownRobot(id))?;
+        if self.robots[index].power != Power::Working {
+            Err(SimError::RobotCharging(id))?;
+        }
+        self.abandon_task(index);
+        let robot = &mut self.robots[index];
+        robot.follow(points);
+        robot.task_started_ms = Some(self.elapsed_ms);
+        let end = (robot.dest_x, robot.dest_y);
+        let length = robot
+            .route
+            .as_ref()
+            .map_or(0.0, |route| route.path.length());
+        self.log_robot_event(index, EventKind::PathAssigned, end, length);
+        self.sync_robot_view();
+        self.record(|| Input::SetRobotPath(id, path.to_vec()));
+        Ok(())
+    }
+
+    /// Stop following the current path and go back to wandering, returning false if the id is
+    /// unknown or the robot is busy charging (its charger route is kept)
+    pub fn clear_robot_path(&mut self, id: u32) -> bool {
+        match self.robots.iter().position(|robot| robot.id == id) {
+            Some(index) if self.robots[index].power != Power::Working => false,
+            Some(index) => {
+                self.abandon_task(index);
+                self.robots[index].route = None;
+                self.record(|| Input::ClearRobotPath(id));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Output: [distanceAlong, distanceRemaining, done], empty if the robot has no path
+    pub fn robot_path_progress(&self, id: u32) -> Result<Vec<f32>, ExportError> {
+        let robot = self.find_robot(id)?;
+        Ok(robot
+            .route
+            .as_ref()
+            .map(|route| {
+                vec![
+                    route.progress,
+                    route.remaining,
+                    if route.done { 1.0 } else { 0.0 },
+                ]
+            })
+            .unwrap_or_default())
+    }
+
+    /// Place charging stations, [x1, y1, x2, y2, ...] in world coordinates. Robots whose battery
+    /// drops below the low threshold drive to the nearest free charger, or wait in place in a
+    /// first-come, first-served queue while all are taken. Replacing the chargers sends every
+    /// robot back to work; those still low look for a charger again on the next tick.
+    pub fn set_chargers(&mut self, chargers: &[f32]) -> Result<(), ExportError> {
+        require_stride("chargers", chargers, 2)?;
+        self.chargers = Chargers::new(unpack_points(chargers, &self.config));
+        for index in 0..self.robots.len() {
+            let robot = &mut self.robots[index];
+            if let Power::ToCharger(_) = robot.power {
+                robot.route = None;
+            }
+            self.set_power(index, Power::Working);
+        }
+        self.record(|| Input::SetChargers(chargers.to_vec()));
+        Ok(())
+    }
+
+    /// Output per charger: [x, y, robotId], robotId -1 while the charger is free
+    pub fn charger_states(&self) -> Vec<f32> {
+        let mut output = Vec::with_capacity(self.chargers.len() * 3);
+        for charger in 0..self.chargers.len() {
+            let (x, y) = self.chargers.position(charger);
+            let (x, y) = self.config.transform_coords(x, y);
+            let occupant = self.chargers.occupant(charger).map_or(-1.0, |id| id as f32);
+            output.extend_from_slice(&[x, y, occupant]);
+        }
+        output
+    }
+
+    /// Ids of robots waiting for a charger, first in line first
+    pub fn charger_queue(&self) -> Vec<u32> {
+        self.chargers.queue().iter().copied().collect()
+    }
+
+    /// Rates are fractions of a full battery: drain per world unit driven, drain per second
+    /// standing still, and charge per second on a charger. Robots below `low_threshold` go
+    /// to charge.
+    pub fn set_battery_model(
+        &mut self,
+        drain_per_unit: f32,
+        idle_drain_per_second: f32,
+        charge_per_second: f32,
+        low_threshold: f32,
+    ) {
+        self.battery = BatteryModel {
+            drain_per_unit,
+            idle_drain_per_second,
+            charge_per_second,
+            low_threshold,
+        };
+        self.record(|| {
+            Input::SetBatteryModel([
+                drain_per_unit,
+                idle_drain_per_second,
+                charge_per_second,
+                low_threshold,
+            ])
+        });
+    }
+
+    /// Set a robot's battery level (0 empty, 1 full), returning false if the id is unknown
+    pub fn set_robot_battery(&mut self, id: u32, level: f32) -> bool {
+        match self.robots.iter_mut().find(|robot| robot.id == id) {
+            Some(robot) => {
+                robot.battery = level.clamp(0.0, 1.0);
+                self.sync_robot_view();
+                self.record(|| Input::SetRobotBattery(id, level));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Serialize the complete state (layout, products, robots with their paths and batteries,
+    /// chargers, the clock and the random source) to a compact binary blob. A simulation
+    /// rebuilt with `Simulation.restore` continues exactly as this one would. Undrained events
+    /// and an active recording are not included.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut out = SnapshotWriter::new();
+        for field in self.config.to_buffer() {
+            out.f32(field);
+        }
+        out.u64(self.rng.state());
+        out.f64(self.elapsed_ms);
+        out.f64(self.stats_since_ms);
+        self.retired_stats.write(&mut out);
+        out.f32(self.battery.drain_per_unit);
+        out.f32(self.battery.idle_drain_per_second);
+        out.f32(self.battery.charge_per_second);
+        out.f32(self.battery.low_threshold);
+
+        // Removed products keep their slot so ids stay stable
+        out.count(self.products.id_count());
+        for id in 0..self.products.id_count() as u32 {
+            let position = self.products.position(id);
+            out.bool(position.is_some());
+            out.point(position.unwrap_or_default());
+        }
+
+        self.chargers.write(&mut out);
+        self.heatmap.write(&mut out);
+        out.u32(self.next_robot_id);
+        out.count(self.robots.len());
+        for robot in &self.robots {
+            robot.write(&mut out);
+        }
+        out.finish()
+    }
+
+    /// Rebuild a simulation from a `snapshot` blob
+    pub fn restore(snapshot: &[u8]) -> Result<Simulation, ExportError> {
+        let sim = Simulation::read_snapshot(snapshot)?;
+        Ok(sim)
+    }
+
+    /// Events dropped because `drain_events` was not called often enough
+    pub fn dropped_event_count(&self) -> usize {
+        self.events.dropped()
+    }
+
+    /// Start logging every input along with the current state, so the session can be
+    /// re-run with `Simulation.replay`. The recording keeps every input and event until
+    /// `finish_recording`, so record bounded sessions.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Recording {
+            start: self.snapshot(),
+            ..Recording::default()
+        });
+    }
+
+    /// Stop recording and return the recording blob
+    pub fn finish_recording(&mut self) -> Result<Vec<u8>, ExportError> {
+        let recording = self.recording.take().ok_or(SimError::NotRecording)?;
+        Ok(recording.encode())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Re-run a `finish_recording` blob from its starting state and check that it produces
+    /// the same events.
+    /// Output: [matches, recordedCount, replayedCount, firstMismatch], firstMismatch -1 when
+    /// every event matches
+    pub fn replay(recording: &[u8]) -> Result<Vec<f32>, ExportError> {
+        let report = Recording::decode(recording)?.replay()?;
+        Ok(report.to_buffer())
+    }
+
+    /// Totals for one robot since it was added or stats were last reset
+    pub fn robot_stats(&self, id: u32) -> Result<RobotStats, ExportError> {
+        Ok(self.find_robot(id)?.stats)
+    }
+
+    /// Fleet-wide totals and rates since stats were last reset, for comparing layouts
+    pub fn fleet_stats(&self) -> FleetStats {
+        let mut totals = self.retired_stats;
+        for robot in &self.robots {
+            totals.add(&robot.stats);
+        }
+        FleetStats::new(
+            self.robots.len() as u32,
+            self.elapsed_ms - self.stats_since_ms,
+            &totals,
+        )
+    }
+
+    /// Zero every robot's stats and the fleet totals, e.g. after a warm-up period. Tasks
+    /// already under way still count when they complete, with their full cycle time.
+    pub fn reset_stats(&mut self) {
+        self.stats_since_ms = self.elapsed_ms;
+        self.retired_stats = RobotStats::default();
+        self.heatmap = TrafficHeatmap::new(&self.nav_grid);
+        for robot in &mut self.robots {
+            robot.stats = RobotStats::default();
+        }
+    }
+
+    /// Robot time spent in each nav grid cell since stats were last reset, for spotting
+    /// bottlenecks.
+    /// Output: [cols, rows, cellSize, minX, minY, then robot-ms per cell], cells row-major
+    /// in the world frame with cols along x; cell (col, row) is centred at
+    /// (minX + (col + 0.5) * cellSize, minY + (row + 0.5) * cellSize)
+    pub fn occupancy_heatmap(&self) -> Vec<f32> {
+        self.heatmap.occupancy_buffer(&self.config)
+    }
+
+    /// Robot time spent blocked in each nav grid cell, i.e. where robots pile up.
+    /// Output: same layout and cells as `occupancy_heatmap`
+    pub fn wait_heatmap(&self) -> Vec<f32> {
+        self.heatmap.wait_buffer(&self.config)
+    }
+
+    /// Simulated time in ms, advanced by every `step`
+    pub fn elapsed_ms(&self) -> f64 {
+        self.elapsed_ms
+    }
+
+    /// Output per event since the last call: [timeMs, robotId, kind, x, y, value]
+    /// Kind: 0 destination changed, 1 collision with the product at (x, y), 2 stuck timeout,
+    /// 3 waypoint reached, 4 battery phase changed (value 0 working, 1 driving to a charger,
+    /// 2 waiting for a charger, 3 charging), 5 path assigned (at its end, value its length),
+    /// 6 task completed (value the cycle time in ms), 7 task abandoned (value the ms spent on
+    /// it). Value is 0 for the other kinds.
+    /// Call it regularly: at most `MAX_PENDING_EVENTS` (65536) are kept, and older ones are
+    /// dropped and counted in `dropped_event_count`.
+    pub fn drain_events(&mut self) -> Vec<f32> {
+        let mut output = Vec::with_capacity(self.events.len() * EVENT_STRIDE);
+        for event in self.events.drain() {
+            output.extend_from_slice(&event.to_world(&self.config));
+        }
+        output
+    }
+
+    /// Advance every robot by one tick, keeping robots from overlapping each other
+    pub fn step(&mut self, delta_ms: f32) {
+        self.record(|| Input::Step(delta_ms));
+        let delta = if delta_ms > 0.0 {
+            delta_ms
+        } else {
+            UPDATE_INTERVAL_DEFAULT
+        };
+        self.elapsed_ms += delta as f64;
+
+        let rng = &mut self.rng;
+        let mut results = std::mem::take(&mut self.step_results);
+        let mut outcomes = std::mem::take(&mut self.step_outcomes);
+        results.clear();
+        outcomes.clear();
+        outcomes.resize(self.robots.len(), TickOutcome::default());
+        results.extend(
+            self.robots
+                .iter_mut()
+                .zip(&mut outcomes)
+                .map(|(robot, outcome)| {
+                    if robot.is_parked() {
+                        robot.drive.velocity = 0.0;
+                        let mut state = robot.state();
+                        state[6] = 0.0;
+                        return state;
+                    }
+                    if robot.route.is_some() {
+                        return follow_route(robot, delta, outcome);
+                    }
+                    update_single_robot(
+                        robot.x,
+                        robot.y,
+                        robot.dest_x,
+                        robot.dest_y,
+                        robot.orientation,
+                        robot.speed,
+                        robot.last_move_ms,
+                        robot.battery,
+                        &self.products,
+                        &self.config,
+                        delta,
+                        rng,
+                        Some(&mut robot.drive),
+                        outcome,
+                    )
+                }),
+        );
+        let mut planned = std::mem::take(&mut self.step_planned);
+        planned.clear();
+        planned.extend(results.iter().map(|result| (result[0], result[1])));
+        separate_robots(&mut results, &self.config);
+
+        let time_ms = self.elapsed_ms;
+        for (((robot, result), outcome), planned) in self
+            .robots
+            .iter_mut()
+            .zip(&results)
+            .zip(&outcomes)
+            .zip(&planned)
+        {
+            let id = robot.id;
+            let mut log = |kind, (x, y), value| {
+                let event = SimEvent {
+                    time_ms,
+                    robot: id,
+                    kind,
+                    x,
+                    y,
+                    value,
+                };
+                log_event(&mut self.events, &mut self.recording, event);
+            };
+            if outcome.stuck {
+                log(EventKind::StuckTimeout, (robot.x, robot.y), 0.0);
+            }
+            if let Some(product) = outcome.collision {
+                log(EventKind::ProductCollision, product, 0.0);
+            }
+            if let Some(waypoint) = outcome.waypoint {
+                log(EventKind::WaypointReached, waypoint, 0.0);
+            }
+            if (result[2], result[3]) != (robot.dest_x, robot.dest_y) {
+                log(EventKind::DestinationChanged, (result[2], result[3]), 0.0);
+            }
+
+            let driven = (result[0] - robot.x).hypot(result[1] - robot.y);
+            let route_done = robot.route.as_ref().is_some_and(|route| route.done);
+            let activity = Activity::classify(driven, robot.is_parked() || route_done);
+            robot.stats.record_tick(activity, driven, delta);
+            self.heatmap.record(
+                &self.nav_grid,
+                (result[0], result[1]),
+                activity == Activity::Blocked,
+                delta,
+            );
+            if outcome.collision.is_some() || (result[0], result[1]) != *planned {
+                robot.stats.collisions_avoided += 1;
+            }
+            if route_done && let Some(started) = robot.task_started_ms.take() {
+                let cycle_ms = time_ms - started;
+                robot.stats.complete_task(cycle_ms);
+                log(
+                    EventKind::TaskCompleted,
+                    (result[0], result[1]),
+                    cycle_ms as f32,
+                );
+            }
+            robot.battery = match robot.power {
+                Power::Charging(_) => self.battery.charge(robot.battery, delta),
+                _ => self.battery.drain(robot.battery, driven, delta),
+            };
+            robot.x = result[0];
+            robot.y = result[1];
+            robot.dest_x = result[2];
+            robot.dest_y = result[3];
+            robot.orientation = result[4];
+            robot.speed = result[5];
+            robot.last_move_ms = result[6];
+        }
+        self.step_results = results;
+        self.step_outcomes = outcomes;
+        self.step_planned = planned;
+        self.update_power();
+        self.sync_robot_view();
+    }
+
+    /// Output: [version, x, y, destX, destY, orientation, speed, lastMoveTime, battery]
+    pub fn robot_state(&self, id: u32) -> Result<Vec<f32>, ExportError> {
+        let robot = self.find_robot(id)?;
+        let mut output = ROBOT_LAYOUT.with_header(1);
+        output.extend_from_slice(&self.robot_to_world(robot));
+        Ok(output)
+    }
+
+    /// Output: [version, then per robot: x, y, destX, destY, orientation, speed, lastMoveTime, battery], ordered like `robot_ids`
+    /// Copies the state out; use `robot_states_ptr` for a view without the copy
+    pub fn robot_states(&self) -> Vec<f32> {
+        self.robot_view.clone()
+    }
+
+    /// Address of the robot states (same layout as `robot_states`) in wasm memory, for building
+    /// a `Float32Array` view without copying. The view follows every tick; it only moves when
+    /// robots are added or removed or wasm memory grows, so rebuild it when
+    /// `robot_states_len` changes or its buffer is detached.
+    pub fn robot_states_ptr(&self) -> *const f32 {
+        self.robot_view.as_ptr()
+    }
+
+    /// Number of floats behind `robot_states_ptr`
+    pub fn robot_states_len(&self) -> usize {
+        self.robot_view.len()
+    }
+
+    /// Address of the world-frame product positions, [x, y] per product id, in wasm memory.
+    /// Removed products read as NaN. Rebuild the view when `products_len` changes or its
+    /// buffer is detached.
+    pub fn products_ptr(&self) -> *const f32 {
+        self.product_view.as_ptr()
+    }
+
+    /// Number of floats behind `products_ptr`
+    pub fn products_len(&self) -> usize {
+        self.product_view.len()
+    }
+
+    /// Compute a path on the cached nav grid, which avoids the current products
+    /// Connectivity: 4 for orthogonal moves only, 8 to also allow diagonal moves
+    /// Smooth: drop waypoints that are not needed for line of sight on the nav grid
+    /// Output: [reachable, x1, y1, x2, y2, ...]; unreachable goals get a straight-line fallback
+    pub fn compute_path(
+        &mut self,
+        start: &[f32],
+        end: &[f32],
+        prefer_outer_walkway: bool,
+        connectivity: u8,
+        smooth: bool,
+    ) -> Result<Vec<f32>, ExportError> {
+        require_len("start", start, 2)?;
+        require_len("end", end, 2)?;
+        let options = PathOptions {
+            prefer_outer_walkway,
+            connectivity: Connectivity::from_neighbor_count(connectivity),
+            smooth,
+        };
+        let start_pt = self.config.transform_coords(start[0], start[1]);
+        let end_pt = self.config.transform_coords(end[0], end[1]);
+
+        self.refresh_nav_grid();
+        let path = self.search.plan(start_pt, end_pt, &self.nav_grid, options);
+
+        Ok(path.to_buffer(&self.config))
+    }
+
+    /// Plan conflict-free paths for a batch of robots on the cached nav grid
+    /// Input per robot: [startX, startY, goalX, goalY]
+    /// Output per robot: [conflictFree, stepCount, x0, y0, x1, y1, ...], one waypoint per time step
+    pub fn compute_paths(
+        &mut self,
+        requests: &[f32],
+        connectivity: u8,
+    ) -> Result<Vec<f32>, ExportError> {
+        require_stride("requests", requests, 4)?;
+        let requests = unpack_path_requests(requests, &self.config);
+        let connectivity = Connectivity::from_neighbor_count(connectivity);
+        self.refresh_nav_grid();
+        let paths =
+            plan_cooperative_paths(&requests, &self.nav_grid, connectivity, &mut self.search);
+        Ok(pack_timed_paths(&paths, &self.config))
+    }
+
+    /// Closest reachable walkway pose from which the robot can pick the product, facing its shelf
+    /// Output: [reachable, x, y, orientation]
+    pub fn pickup_pose_for_product(
+        &mut self,
+        robot_id: u32,
+        product_id: u32,
+        connectivity: u8,
+    ) -> Result<Vec<f32>, ExportError> {
+        let robot = self.find_robot(robot_id)?;
+        let from = (robot.x, robot.y);
+        let product = self
+            .products
+            .position(product_id)
+            .ok_or(SimError::UnknownProduct(product_id))?;
+        let connectivity = Connectivity::from_neighbor_count(connectivity);
+
+        self.refresh_nav_grid();
+        let pose = pickup_pose(
+            product,
+            from,
+            &self.nav_grid,
+            connectivity,
+            &mut self.search,
+        );
+        Ok(pose.to_buffer(&self.config))
+    }
+
+    /// Plan one trip for a robot that picks several products and then goes to a drop-off,
+    /// visiting the products in the order that keeps aisle travel short
+    /// Drop target: [x, y]
+    /// Output: [reachable, stopCount, (productId, waypointIndex) per stop in visiting order, x1, y1, ...]
+    pub fn plan_pick_route(
+        &mut self,
+        robot_id: u32,
+        product_ids: &[u32],
+        drop_target: &[f32],
+        connectivity: u8,
+    ) -> Result<Vec<f32>, ExportError> {
+        require_len("drop target", drop_target, 2)?;
+        let robot = self.find_robot(robot_id)?;
+        let start = (robot.x, robot.y);
+        let stops = product_ids
+            .iter()
+            .map(|id| {
+                self.products
+                    .position(*id)
+                    .ok_or(SimError::UnknownProduct(*id))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let drop_off = self.config.transform_coords(drop_target[0], drop_target[1]);
+        let connectivity = Connectivity::from_neighbor_count(connectivity);
+
+        self.refresh_nav_grid();
+        let route = plan_pick_route(
+            start,
+            &stops,
+            drop_off,
+            &self.nav_grid,
+            connectivity,
+            &mut self.search,
+        );
+        Ok(pack_pick_route(
+            &route,
+            |stop| product_ids[stop] as f32,
+            &self.config,
+        ))
+    }
+
+    /// Assign the given idle robots to pending pickups, minimising total nav-grid distance
+    /// Tasks: [x1, y1, x2, y2, ...] pickup point for each pending task
+    /// Output per assignment: [robotId, taskIndex, cost, waypointCount, x1, y1, ...]
+    pub fn allocate_tasks(
+        &mut self,
+        robot_ids: &[u32],
+        tasks: &[f32],
+        connectivity: u8,
+    ) -> Result<Vec<f32>, ExportError> {
+        require_stride("tasks", tasks, 2)?;
+        let robots = robot_ids
+            .iter()
+            .map(|id| self.find_robot(*id).map(|robot| (robot.x, robot.y)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let tasks = unpack_points(tasks, &self.config);
+        let connectivity = Connectivity::from_neighbor_count(connectivity);
+        self.refresh_nav_grid();
+        let assignments = allocate_tasks(
+            &robots,
+            &tasks,
+            &self.nav_grid,
+            connectivity,
+            &mut self.search,
+        );
+        Ok(pack_task_assignments(
+            &assignments,
+            |robot| robot_ids[robot] as f32,
+            &self.config,
+        ))
+    }
+}
+
+/// Advance a routed robot one pure-pursuit tick, returning its state in the same layout as
+/// `update_single_robot` so it goes through the same separation pass as wandering robots.
+/// Path points passed during the tick, or the end of the path on arrival, are reported as
+/// reached waypoints.
+fn follow_route(
+    robot: &mut SimRobot,
+    delta: f32,
+    outcome: &mut TickOutcome,
+) -> [f32; ROBOT_STRIDE] {
+    let Some(route) = &mut robot.route else {
+        return robot.state();
+    };
+    let state = FollowState {
+        x: robot.x,
+        y: robot.y,
+        heading: robot.orientation,
+        velocity: robot.drive.velocity,
+        progress: route.progress,
+    };
+    let result = pure_pursuit_step(
+        &route.path,
+        state,
+        robot.speed,
+        PATH_LOOKAHEAD_DEFAULT,
+        &robot.drive.limits,
+        delta,
+    );
+    robot.drive.velocity = result.state.velocity;
+    let points = route.path.points();
+    let passed = route
+        .path
+        .point_distances()
+        .iter()
+        .rposition(|distance| *distance > route.progress && *distance <= result.state.progress);
+    if result.done && !route.done {
+        outcome.waypoint = points.last().copied();
+    } else if let Some(index) = passed {
+        outcome.waypoint = Some(points[index]);
+    }
+    route.progress = result.state.progress;
+    route.remaining = result.remaining;
+    route.done = result.done;
+
+    [
+        result.state.x,
+        result.state.y,
+        robot.dest_x,
+        robot.dest_y,
+        result.state.heading,
+        robot.speed,
+        0.0,
+        robot.battery,
+    ]
+}
+
+/// Queue an event for `drain_events`, and keep it in the recording if one is running
+fn log_event(events: &mut EventQueue, recording: &mut Option<Recording>, event: SimEvent) {
+    events.push(event);
+    if let Some(recording) = recording {
+        recording.events.push(event);
+    }
+}
+
+impl Simulation {
+    /// Start an empty simulation from an already parsed config
+    pub fn with_config(config: StoreConfig) -> Simulation {
+        let nav_grid = build_nav_grid(&config);
+        Simulation {
+            heatmap: TrafficHeatmap::new(&nav_grid),
+            nav_grid,
+            search: PathSearch::new(),
+            config,
+            products: ProductIndex::build(&[], &config),
+            nav_grid_dirty: false,
+            robots: Vec::new(),
+            next_robot_id: 0,
+            rng: Rng::default(),
+            battery: BatteryModel::default(),
+            chargers: Chargers::default(),
+            elapsed_ms: 0.0,
+            stats_since_ms: 0.0,
+            retired_stats: RobotStats::default(),
+            events: EventQueue::default(),
+            recording: None,
+            step_results: Vec::new(),
+            step_outcomes: Vec::new(),
+            step_planned: Vec::new(),
+            robot_view: ROBOT_LAYOUT.with_header(0),
+            product_view: Vec::new(),
+        }
+    }
+
+    pub(crate) fn read_snapshot(snapshot: &[u8]) -> Result<Simulation, SimError> {
+        let mut input = SnapshotReader::new(snapshot)?;
+        let mut config = [0.0; CONFIG_LEN];
+        for field in &mut config {
+            *field = input.f32()?;
+        }
+        let config = StoreConfig::from_buffer(&config);
+        // Checked before the nav grid is sized from it
+        if !config.validate().is_empty() {
+            return Err(SimError::InvalidSnapshot("invalid store layout"));
+        }
+        let mut sim = Simulation::with_config(config);
+        sim.rng = Rng::new(input.u64()?);
+        sim.elapsed_ms = input.f64()?;
+        sim.stats_since_ms = input.f64()?;
+        sim.retired_stats = RobotStats::read(&mut input)?;
+        sim.battery = BatteryModel {
+            drain_per_unit: input.f32()?,
+            idle_drain_per_second: input.f32()?,
+            charge_per_second: input.f32()?,
+            low_threshold: input.f32()?,
+        };
+
+        let product_count = input.count(9)?;
+        for _ in 0..product_count {
+            let active = input.bool()?;
+            let (x, y) = input.point()?;
+            let id = sim.products.insert(x, y);
+            if !active {
+                sim.products.remove(id);
+            }
+        }
+
+        sim.chargers = Chargers::read(&mut input)?;
+        sim.heatmap = TrafficHeatmap::read(&mut input, &sim.nav_grid)?;
+        sim.next_robot_id = input.u32()?;
+        let robot_count = input.count(SimRobot::SNAPSHOT_MIN_SIZE)?;
+        for _ in 0..robot_count {
+            let robot = SimRobot::read(&mut input, sim.chargers.len())?;
+            sim.robots.push(robot);
+        }
+        input.finish()?;
+
+        sim.rebuild_nav_grid();
+        sim.sync_robot_view();
+        sim.sync_product_view();
+        Ok(sim)
+    }
+
+    /// Rebuild the nav grid from the current layout, blocking cells around products
+    fn rebuild_nav_grid(&mut self) {
+        let mut grid = build_nav_grid(&self.config);
+        grid.block_products(&self.products.to_buffer());
+        self.nav_grid = grid;
+        self.nav_grid_dirty = false;
+    }
+
+    fn find_robot(&self, id: u32) -> Result<&SimRobot, SimError> {
+        self.robots
+            .iter()
+            .find(|robot| robot.id == id)
+            .ok_or(SimError::UnknownRobot(id))
+    }
+
+    fn refresh_nav_grid(&mut self) {
+        if self.nav_grid_dirty {
+            self.rebuild_nav_grid();
+        }
+    }
+
+    /// Rewrite the robot view in place; only reallocates when the fleet outgrows it
+    fn sync_robot_view(&mut self) {
+        let mut view = std::mem::take(&mut self.robot_view);
+        view.truncate(HEADER_LEN);
+        for robot in &self.robots {
+            view.extend_from_slice(&self.robot_to_world(robot));
+        }
+        self.robot_view = view;
+    }
+
+    fn sync_product_view(&mut self) {
+        self.product_view.clear();
+        for id in 0..self.products.id_count() as u32 {
+            let (x, y) = match self.products.position(id) {
+                Some((x, y)) => self.config.transform_coords(x, y),
+                None => (f32::NAN, f32::NAN),
+            };
+            self.product_view.extend_from_slice(&[x, y]);
+        }
+    }
+
+    fn robot_to_world(&self, robot: &SimRobot) -> [f32; ROBOT_STRIDE] {
+        let mut state = robot.state();
+        (state[0], state[1]) = self.config.transform_coords(robot.x, robot.y);
+        (state[2], state[3]) = self.config.transform_coords(robot.dest_x, robot.dest_y);
+        state[4] = self.config.transform_orientation(robot.orientation);
+        state
+    }
+
+    /// Send low robots to chargers, start charging on arrival, and hand chargers that free
+    /// up to the queue
+    fn update_power(&mut self) {
+        if self.chargers.is_empty() {
+            return;
+        }
+        for index in 0..self.robots.len() {
+            let robot = &mut self.robots[index];
+            match robot.power {
+                Power::Working if self.battery.is_low(robot.battery) => {
+                    self.send_to_charger(index);
+                }
+                Power::ToCharger(charger) => {
+                    let (x, y) = self.chargers.position(charger);
+                    let arrived = match &robot.route {
+                        Some(route) if !route.done => continue,
+                        // The end of the path is as close as the grid gets it, even when
+                        // separation nudged it off the charger or the charger is off the
+                        // walkways, so it docks there instead of re-planning every tick
+                        Some(_) => true,
+                        None => (robot.x - x).hypot(robot.y - y) <= ARRIVAL_DISTANCE,
+                    };
+                    if arrived {
+                        robot.route = None;
+                        self.set_power(index, Power::Charging(charger));
+                    } else {
+                        // No path to follow; plan one to the charger
+                        self.refresh_nav_grid();
+                        let robot = &mut self.robots[index];
+                        let route = self.search.find_path(
+                            (robot.x, robot.y),
+                            (x, y),
+                            &self.nav_grid,
+                            Connectivity::Eight,
+                        );
+                        robot.follow(append_goal(route.waypoints, (x, y)));
+                    }
+                }
+                Power::Charging(_) if robot.battery >= 1.0 => {
+                    if robot.route.is_none() {
+                        (robot.dest_x, robot.dest_y) =
+                            get_valid_destination(&self.config, &mut self.rng);
+                    }
+                    robot.last_move_ms = 0.0;
+                    let id = robot.id;
+                    self.set_power(index, Power::Working);
+                    self.chargers.release(id);
+                    self.serve_charger_queue();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Route a robot to the nearest free charger it can reach, or queue it if there is none
+    fn send_to_charger(&mut self, index: usize) {
+        self.refresh_nav_grid();
+        // Whatever path it was on is given up for the charger
+        self.abandon_task(index);
+        let robot = &mut self.robots[index];
+        let nearest = self.chargers.nearest_free(
+            (robot.x, robot.y),
+            &self.nav_grid,
+            Connectivity::Eight,
+            &mut self.search,
+        );
+        match nearest {
+            Some((charger, route)) => {
+                self.chargers.claim(charger, robot.id);
+                robot.follow(route.waypoints);
+                self.set_power(index, Power::ToCharger(charger));
+            }
+            None => {
+                self.chargers.enqueue(robot.id);
+                self.set_power(index, Power::Queued);
+            }
+        }
+    }
+
+    /// Change a robot's battery phase, logging a `PhaseChanged` event if it differs
+    fn set_power(&mut self, index: usize, power: Power) {
+        let robot = &mut self.robots[index];
+        if robot.power == power {
+            return;
+        }
+        robot.power = power;
+        let position = (robot.x, robot.y);
+        self.log_robot_event(
+            index,
+            EventKind::PhaseChanged,
+            position,
+            power.code() as f32,
+        );
+    }
+
+    /// Drop the robot's unfinished `set_robot_path` task, if it has one, logging that it was
+    /// abandoned
+    fn abandon_task(&mut self, index: usize) {
+        let robot = &mut self.robots[index];
+        if let Some(started) = robot.task_started_ms.take() {
+            let position = (robot.x, robot.y);
+            let spent_ms = (self.elapsed_ms - started) as f32;
+            self.log_robot_event(index, EventKind::TaskAbandoned, position, spent_ms);
+        }
+    }
+
+    /// Log an event for the robot at `index`, stamped with the current time
+    fn log_robot_event(&mut self, index: usize, kind: EventKind, (x, y): (f32, f32), value: f32) {
+        let event = SimEvent {
+            time_ms: self.elapsed_ms,
+            robot: self.robots[index].id,
+            kind,
+            x,
+            y,
+            value,
+        };
+        log_event(&mut self.events, &mut self.recording, event);
+    }
+
+    /// Log an input while recording; `input` is only built when it is needed
+    fn record(&mut self, input: impl FnOnce() -> Input) {
+        if let Some(recording) = &mut self.recording {
+            recording.inputs.push(input());
+        }
+    }
+
+    /// Keep every event from now on, unbounded, for comparing a replay against its recording
+    pub(crate) fn capture_events(&mut self) {
+        self.recording = Some(Recording::default());
+    }
+
+    /// Events kept since `capture_events`, in the store frame
+    pub(crate) fn captured_events(&mut self) -> Vec<SimEvent> {
+        self.recording
+            .take()
+            .map(|recording| recording.events)
+            .unwrap_or_default()
+    }
+
+    /// Give a freed charger to the robot that has waited longest, skipping queued ids whose
+    /// robot no longer exists so the charger does not sit idle
+    fn serve_charger_queue(&mut self) {
+        while let Some(id) = self.chargers.dequeue() {
+            if let Some(index) = self.robots.iter().position(|robot| robot.id == id) {
+                self.send_to_charger(index);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::layout::LAYOUT_VERSION;
+
+    /// Robots wandering the default store, one following a path and one low enough to queue
+    /// for the only charger, so a run exercises the random source, paths and batteries
+    pub(crate) fn busy_simulation(seed: u32) -> Simulation {
+        let mut sim = Simulation::with_config(StoreConfig::from_buffer(&[]));
+        sim.set_seed(seed);
+        sim.set_products(&[-65.0, 30.0, -25.0, -20.0, 15.0, 5.0]);
+        assert!(sim.set_chargers(&[35.0, 0.0]).is_ok());
+        for (i, aisle_x) in [-85.0, -45.0, -5.0, 35.0, 75.0].into_iter().enumerate() {
+            let edge_y = if i % 2 == 0 { 60.0 } else { -60.0 };
+            let speed = 2.0 + i as f32 * 0.5;
+            sim.add_robot(aisle_x, edge_y, aisle_x, -edge_y, 0.0, speed, (i % 3) as u8);
+        }
+        assert!(
+            sim.set_robot_path(1, &[-45.0, -60.0, -45.0, 0.0, -45.0, 60.0])
+                .is_ok()
+        );
+        assert!(sim.set_robot_battery(3, 0.1));
+        sim
+    }
+
+    #[test]
+    fn set_config_keeps_product_ids() {
+        let mut sim = Simulation::with_config(StoreConfig::from_buffer(&[]));
+        sim.set_products(&[-65.0, 30.0, -25.0, -20.0, 15.0, 5.0]);
+        assert!(sim.remove_product(1));
+
+        let mut horizontal = StoreConfig::from_buffer(&[]);
+        horizontal.orientation = Orientation::Horizontal;
+        let mut config = vec![LAYOUT_VERSION as f32];
+        config.extend(horizontal.to_buffer());
+        assert!(sim.set_config(&config).is_ok());
+
+        assert_eq!(sim.products.id_count(), 3);
+        assert_eq!(sim.products.position(2), Some((5.0, 15.0)));
+        assert_eq!(sim.product_view[4..], [15.0, 5.0]);
+        assert!(sim.product_view[2..4].iter().all(|v| v.is_nan()));
+        assert!(!sim.remove_product(1));
+        assert!(sim.remove_product(2));
+        assert_eq!(sim.product_count(), 1);
+        assert_eq!(sim.products.position(0), Some((30.0, -65.0)));
+    }
+
+    /// Step until `done` holds, failing after `max_ticks`
+    fn step_until(sim: &mut Simulation, max_ticks: usize, done: impl Fn(&Simulation) -> bool) {
+        for _ in 0..max_ticks {
+            if done(sim) {
+                return;
+            }
+            sim.step(50.0);
+        }
+        panic!("condition not reached in {max_ticks} ticks");
+    }
+
+    /// Robots spread along the charger's aisle, all low enough to need the single charger
+    fn low_robots_and_one_charger(count: usize) -> (Simulation, Vec<u32>) {
+        let mut sim = Simulation::with_config(StoreConfig::from_buffer(&[]));
+        assert!(sim.set_chargers(&[35.0, 0.0]).is_ok());
+        let ids: Vec<u32> = (0..count)
+            .map(|i| {
+                let y = 60.0 - i as f32 * 30.0;
+                let id = sim.add_robot(35.0, y, 35.0, y, 0.0, 3.0, 0);
+                assert!(sim.set_robot_battery(id, 0.1 + i as f32 * 0.01));
+                id
+            })
+            .collect();
+        (sim, ids)
+    }
+
+    #[test]
+    fn low_robots_queue_for_a_charger_and_charge_to_full() {
+        let (mut sim, ids) = low_robots_and_one_charger(2);
+        sim.step(50.0);
+        assert_eq!(sim.robots[0].power, Power::ToCharger(0));
+        assert_eq!(sim.robots[1].power, Power::Queued);
+        assert!(sim.chargers.queue().iter().eq([&ids[1]]));
+
+        step_until(&mut sim, 2000, |sim| {
+            sim.robots[0].power == Power::Charging(0)
+        });
+        // Driving to the charger used some charge, standing in the queue a little
+        assert!(sim.robots[0].battery < 0.1);
+        assert!(sim.robots[1].battery < 0.11);
+
+        step_until(&mut sim, 4000, |sim| sim.robots[0].power == Power::Working);
+        assert_eq!(sim.robots[0].battery, 1.0);
+        assert_eq!(sim.robots[1].power, Power::ToCharger(0));
+        assert!(sim.chargers.queue().is_empty());
+    }
+
+    #[test]
+    fn a_freed_charger_skips_queued_robots_that_no_longer_exist() {
+        let (mut sim, ids) = low_robots_and_one_charger(3);
+        sim.step(50.0);
+        assert!(sim.chargers.queue().iter().eq(&ids[1..]));
+
+        // Left behind in the queue, e.g. by a restored snapshot
+        sim.robots.retain(|robot| robot.id != ids[1]);
+        step_until(&mut sim, 6000, |sim| sim.robots[0].power == Power::Working);
+        assert_eq!(sim.robots[1].power, Power::ToCharger(0));
+        assert!(sim.chargers.queue().is_empty());
+    }
+
+    #[test]
+    fn same_seed_gives_identical_trajectories() {
+        let (mut a, mut b) = (busy_simulation(9), busy_simulation(9));
+        for _ in 0..1200 {
+            a.step(50.0);
+            b.step(50.0);
+            assert_eq!(a.robot_states(), b.robot_states());
+        }
+        assert_eq!(a.drain_events(), b.drain_events());
+        assert_eq!(a.snapshot(), b.snapshot());
+
+        let mut other = busy_simulation(10);
+        for _ in 0..1200 {
+            other.step(50.0);
+        }
+        assert_ne!(a.robot_states(), other.robot_states());
+    }
+}
@@ -0,0 +1,265 @@
+use crate::error::SimError;
+
+/// First bytes of every snapshot, so unrelated blobs are rejected up front
+const SNAPSHOT_MAGIC: [u8; 4] = *b"TSIM";
+
+/// Version written after the magic. Bump it whenever the encoding of any field changes;
+/// snapshots of other versions are rejected rather than misread.
+pub const SNAPSHOT_VERSION: u32 = 4;
+
+/// Little-endian encoder for simulation snapshots and other blobs built from the same fields
+pub struct SnapshotWriter {
+    bytes: Vec<u8>,
+}
+
+impl SnapshotWriter {
+    /// Start a snapshot with the magic and version already written
+    pub fn new() -> Self {
+        SnapshotWriter::with_header(SNAPSHOT_MAGIC, SNAPSHOT_VERSION)
+    }
+
+    /// Start a blob of another kind, identified by its own magic and version
+    pub fn with_header(magic: [u8; 4], version: u32) -> Self {
+        let mut writer = SnapshotWriter { bytes: Vec::new() };
+        writer.bytes.extend_from_slice(&magic);
+        writer.u32(version);
+        writer
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn f32(&mut self, value: f32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn f64(&mut self, value: f64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Length-prefixed byte string, e.g. a nested snapshot
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.count(bytes.len());
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn count(&mut self, count: usize) {
+        self.u32(count as u32);
+    }
+
+    pub fn point(&mut self, (x, y): (f32, f32)) {
+        self.f32(x);
+        self.f32(y);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl Default for SnapshotWriter {
+    fn default() -> Self {
+        SnapshotWriter::new()
+    }
+}
+
+/// Decoder matching `SnapshotWriter`; every read fails cleanly on a truncated blob
+pub struct SnapshotReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> SnapshotReader<'a> {
+    /// Check the magic and version and position the reader on the first field
+    pub fn new(bytes: &'a [u8]) -> Result<Self, SimError> {
+        SnapshotReader::with_header(bytes, SNAPSHOT_MAGIC, SNAPSHOT_VERSION)
+    }
+
+    /// Reader for a blob written with `SnapshotWriter::with_header`
+    pub fn with_header(bytes: &'a [u8], magic: [u8; 4], version: u32) -> Result<Self, SimError> {
+        let mut reader = SnapshotReader { bytes };
+        if reader.take(magic.len())? != magic {
+            return Err(SimError::InvalidSnapshot("unrecognised blob type"));
+        }
+        if reader.u32()? != version {
+            return Err(SimError::InvalidSnapshot("unsupported blob version"));
+        }
+        Ok(reader)
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], SimError> {
+        if self.bytes.len() < count {
+            return Err(SimError::InvalidSnapshot("snapshot is truncated"));
+        }
+        let (head, tail) = self.bytes.split_at(count);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SimError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SimError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, SimError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SimError::InvalidSnapshot("invalid flag")),
+        }
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SimError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SimError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub fn f32(&mut self) -> Result<f32, SimError> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    pub fn f64(&mut self) -> Result<f64, SimError> {
+        Ok(f64::from_le_bytes(self.array()?))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], SimError> {
+        let count = self.count(1)?;
+        self.take(count)
+    }
+
+    /// Element count written by `SnapshotWriter::count`, checked against the bytes left so a
+    /// corrupt count cannot trigger a huge allocation
+    pub fn count(&mut self, min_element_size: usize) -> Result<usize, SimError> {
+        let count = self.u32()? as usize;
+        if count.saturating_mul(min_element_size) > self.bytes.len() {
+            return Err(SimError::InvalidSnapshot("snapshot is truncated"));
+        }
+        Ok(count)
+    }
+
+    pub fn point(&mut self) -> Result<(f32, f32), SimError> {
+        Ok((self.f32()?, self.f32()?))
+    }
+
+    /// Fail if anything follows the last field
+    pub fn finish(self) -> Result<(), SimError> {
+        if !self.bytes.is_empty() {
+            return Err(SimError::InvalidSnapshot("unexpected data after snapshot"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::simulation::tests::busy_simulation;
+
+    /// Snapshot taken mid-run, with robots on paths, charging and queued
+    fn mid_run_snapshot() -> (Simulation, Vec<u8>) {
+        let mut sim = busy_simulation(4);
+        for _ in 0..300 {
+            sim.step(50.0);
+        }
+        // Undrained events are not part of a snapshot
+        sim.drain_events();
+        let snapshot = sim.snapshot();
+        (sim, snapshot)
+    }
+
+    #[test]
+    fn restored_simulation_continues_identically() {
+        let (mut original, snapshot) = mid_run_snapshot();
+        let mut restored = Simulation::read_snapshot(&snapshot).unwrap();
+        assert_eq!(restored.snapshot(), snapshot);
+
+        for _ in 0..600 {
+            original.step(50.0);
+            restored.step(50.0);
+            assert_eq!(original.robot_states(), restored.robot_states());
+        }
+        assert_eq!(original.drain_events(), restored.drain_events());
+        assert_eq!(original.snapshot(), restored.snapshot());
+    }
+
+    #[test]
+    fn truncated_snapshots_are_rejected() {
+        let (_, snapshot) = mid_run_snapshot();
+        for len in 0..snapshot.len() {
+            assert!(
+                Simulation::read_snapshot(&snapshot[..len]).is_err(),
+                "{len} bytes"
+            );
+        }
+        let mut extended = snapshot.clone();
+        extended.push(0);
+        assert_eq!(
+            Simulation::read_snapshot(&extended).err(),
+            Some(SimError::InvalidSnapshot("unexpected data after snapshot"))
+        );
+    }
+
+    #[test]
+    fn foreign_and_outdated_blobs_are_rejected() {
+        let (_, snapshot) = mid_run_snapshot();
+        let mut foreign = snapshot.clone();
+        foreign[..4].copy_from_slice(b"TREC");
+        assert_eq!(
+            Simulation::read_snapshot(&foreign).err(),
+            Some(SimError::InvalidSnapshot("unrecognised blob type"))
+        );
+
+        let mut outdated = snapshot.clone();
+        outdated[4..8].copy_from_slice(&(SNAPSHOT_VERSION - 1).to_le_bytes());
+        assert_eq!(
+            Simulation::read_snapshot(&outdated).err(),
+            Some(SimError::InvalidSnapshot("unsupported blob version"))
+        );
+    }
+
+    #[test]
+    fn oversized_layouts_are_rejected() {
+        let (_, snapshot) = mid_run_snapshot();
+        // The config follows the 8-byte header, store width then store height
+        for (offset, size) in [(8, 1.0e9), (12, 1.0e9), (8, f32::MAX), (12, f32::INFINITY)] {
+            let mut oversized = snapshot.clone();
+            oversized[offset..offset + 4].copy_from_slice(&f32::to_le_bytes(size));
+            assert_eq!(
+                Simulation::read_snapshot(&oversized).err(),
+                Some(SimError::InvalidSnapshot("invalid store layout"))
+            );
+        }
+    }
+
+    #[test]
+    fn corrupt_snapshots_fail_without_panicking() {
+        let (_, snapshot) = mid_run_snapshot();
+        for index in 8..snapshot.len() {
+            let mut corrupt = snapshot.clone();
+            corrupt[index] ^= 0xff;
+            // A flipped bit inside a float can still decode; the reader must just never panic
+            let _ = Simulation::read_snapshot(&corrupt);
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use crate::constants::*;
+
+/// Edge length of a spatial hash bucket; matching the collision radius keeps queries to a 3x3 block
+pub const PRODUCT_INDEX_CELL_SIZE: f32 = PRODUCT_CLEARANCE;
+
+/// Largest index side in buckets, so a huge store cannot demand an unbounded bucket table
+pub const MAX_PRODUCT_INDEX_SIZE: i32 = 1024;
+
+/// Uniform-grid spatial hash over product positions (store frame) covering the store footprint.
+/// Products outside the store are clamped into the edge buckets, so queries stay exact.
+/// Ids are the product's position in the buffer the index was built from; removing a product
+/// keeps the other ids stable and `insert` hands out the next unused id.
+#[derive(Clone, Debug)]
+pub struct ProductIndex {
+    min_x: f32,
+    min_y: f32,
+    cols: i32,
+    rows: i32,
+    buckets: Vec<Vec<u32>>,
+    positions: Vec<(f32, f32)>,
+    active: Vec<bool>,
+}
+
+impl ProductIndex {
+    /// Products format: [x1, y1, x2, y2, ...] in the store frame
+    pub fn build(products: &[f32], config: &StoreConfig) -> Self {
+        // Products past the last bucket clamp into it, so a capped index stays exact
+        let buckets_across = |extent: f32| {
+            ((extent / PRODUCT_INDEX_CELL_SIZE).ceil() as i32).clamp(1, MAX_PRODUCT_INDEX_SIZE)
+        };
+        let cols = buckets_across(config.store_width);
+        let rows = buckets_across(config.store_height);
+        let bucket_count = (cols as usize)
+            .checked_mul(rows as usize)
+            .expect("product index dimensions are capped");
+        let mut index = ProductIndex {
+            min_x: -config.store_width / 2.0,
+            min_y: -config.store_height / 2.0,
+            cols,
+            rows,
+            buckets: vec![Vec::new(); bucket_count],
+            positions: Vec::with_capacity(products.len() / 2),
+            active: Vec::with_capacity(products.len() / 2),
+        };
+        for chunk in products.chunks_exact(2) {
+            index.insert(chunk[0], chunk[1]);
+        }
+        index
+    }
+
+    fn bucket_coords(&self, x: f32, y: f32) -> (i32, i32) {
+        let col = ((x - self.min_x) / PRODUCT_INDEX_CELL_SIZE).floor() as i32;
+        let row = ((y - self.min_y) / PRODUCT_INDEX_CELL_SIZE).floor() as i32;
+        (col.clamp(0, self.cols - 1), row.clamp(0, self.rows - 1))
+    }
+
+    fn bucket_index(&self, x: f32, y: f32) -> usize {
+        let (col, row) = self.bucket_coords(x, y);
+        (row * self.cols + col) as usize
+    }
+
+    /// Add a product and return its id
+    pub fn insert(&mut self, x: f32, y: f32) -> u32 {
+        let id = self.positions.len() as u32;
+        let bucket = self.bucket_index(x, y);
+        self.buckets[bucket].push(id);
+        self.positions.push((x, y));
+        self.active.push(true);
+        id
+    }
+
+    /// Take a product out of the index, e.g. once it has been picked up.
+    /// Returns false if the id is unknown or already removed.
+    pub fn remove(&mut self, id: u32) -> bool {
+        let Some((x, y)) = self.position(id) else {
+            return false;
+        };
+        let bucket = self.bucket_index(x, y);
+        self.buckets[bucket].retain(|entry| *entry != id);
+        self.active[id as usize] = false;
+        true
+    }
+
+    pub fn position(&self, id: u32) -> Option<(f32, f32)> {
+        let idx = id as usize;
+        if self.active.get(idx).copied().unwrap_or(false) {
+            Some(self.positions[idx])
+        } else {
+            None
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.active.iter().filter(|active| **active).count()
+    }
+
+    /// Number of ids handed out so far, including removed products
+    pub fn id_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.active.iter().any(|active| *active)
+    }
+
+    /// Active products packed as [x1, y1, x2, y2, ...]
+    pub fn to_buffer(&self) -> Vec<f32> {
+        self.positions
+            .iter()
+            .zip(&self.active)
+            .filter(|(_, active)| **active)
+            .flat_map(|((x, y), _)| [*x, *y])
+            .collect()
+    }
+
+    /// The same products under a new layout: positions move from `previous`'s store frame to
+    /// `next`'s, while ids and removed slots stay as they are
+    pub fn transformed(&self, previous: &StoreConfig, next: &StoreConfig) -> Self {
+        let mut index = ProductIndex::build(&[], next);
+        for (&(x, y), &active) in self.positions.iter().zip(&self.active) {
+            // Both transforms are their own inverse, so applying the old one restores world coords
+            let (x, y) = previous.transform_coords(x, y);
+            let (x, y) = next.transform_coords(x, y);
+            let id = index.insert(x, y);
+            if !active {
+                index.remove(id);
+            }
+        }
+        index
+    }
+
+    /// Lowest-id product accepted by `hit` among buckets overlapping the given box
+    fn find_in_box(
+        &self,
+        min: (f32, f32),
+        max: (f32, f32),
+        hit: impl Fn(f32, f32) -> bool,
+    ) -> Option<(f32, f32)> {
+        let (min_col, min_row) = self.bucket_coords(min.0, min.1);
+        let (max_col, max_row) = self.bucket_coords(max.0, max.1);
+        let mut best: Option<u32> = None;
+        for row in min_row..=max_row {
+            for col in min_col..=max_col {
+                for id in &self.buckets[(row * self.cols + col) as usize] {
+                    if best.is_some_and(|b| b < *id) {
+                        continue;
+                    }
+                    let (px, py) = self.positions[*id as usize];
+                    if hit(px, py) {
+                        best = Some(*id);
+                    }
+                }
+            }
+        }
+        best.map(|id| self.positions[id as usize])
+    }
+
+    /// First product (by id) within `radius` of a point
+    pub fn find_within(&self, x: f32, y: f32, radius: f32) -> Option<(f32, f32)> {
+        self.find_in_box(
+            (x - radius, y - radius),
+            (x + radius, y + radius),
+            |px, py| {
+                let dx = x - px;
+                let dy = y - py;
+                dx * dx + dy * dy < radius * radius
+            },
+        )
+    }
+
+    /// First product (by id) within `radius` of the segment from `a` to `b`
+    pub fn find_along_segment(
+        &self,
+        a: (f32, f32),
+        b: (f32, f32),
+        radius: f32,
+    ) -> Option<(f32, f32)> {
+        let seg_dx = b.0 - a.0;
+        let seg_dy = b.1 - a.1;
+        let seg_len_sq = seg_dx * seg_dx + seg_dy * seg_dy;
+        self.find_in_box(
+            (a.0.min(b.0) - radius, a.1.min(b.1) - radius),
+            (a.0.max(b.0) + radius, a.1.max(b.1) + radius),
+            |px, py| {
+                let t = if seg_len_sq > 0.0001 {
+                    ((px - a.0) * seg_dx + (py - a.1) * seg_dy) / seg_len_sq
+                } else {
+                    0.0
+                };
+                let clamped_t = t.clamp(0.0, 1.0);
+                let dx = a.0 + seg_dx * clamped_t - px;
+                let dy = a.1 + seg_dy * clamped_t - py;
+                dx * dx + dy * dy < radius * radius
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    fn random_point(rng: &mut Rng, config: &StoreConfig) -> (f32, f32) {
+        // A little past the store edges, to cover the clamped edge buckets
+        let x = (rng.next_f32() - 0.5) * (config.store_width + 20.0);
+        let y = (rng.next_f32() - 0.5) * (config.store_height + 20.0);
+        (x, y)
+    }
+
+    /// Index plus the same products as a plain list, with every third one removed
+    fn random_products(
+        rng: &mut Rng,
+        config: &StoreConfig,
+    ) -> (ProductIndex, Vec<Option<(f32, f32)>>) {
+        let products: Vec<f32> = (0..600)
+            .flat_map(|_| {
+                let (x, y) = random_point(rng, config);
+                [x, y]
+            })
+            .collect();
+        let mut index = ProductIndex::build(&products, config);
+        let mut expected: Vec<_> = products
+            .chunks_exact(2)
+            .map(|chunk| Some((chunk[0], chunk[1])))
+            .collect();
+        for id in (0..expected.len()).step_by(3) {
+            assert!(index.remove(id as u32));
+            expected[id] = None;
+        }
+        (index, expected)
+    }
+
+    /// First product (by id) accepted by `hit`, the answer the index must give
+    fn linear_scan(
+        products: &[Option<(f32, f32)>],
+        hit: impl Fn(f32, f32) -> bool,
+    ) -> Option<(f32, f32)> {
+        products
+            .iter()
+            .flatten()
+            .copied()
+            .find(|(x, y)| hit(*x, *y))
+    }
+
+    #[test]
+    fn find_within_matches_a_linear_scan() {
+        let config = StoreConfig::from_buffer(&[]);
+        let mut rng = Rng::new(7);
+        let (index, products) = random_products(&mut rng, &config);
+
+        for _ in 0..500 {
+            let (x, y) = random_point(&mut rng, &config);
+            for radius in [PRODUCT_CLEARANCE, 7.5] {
+                let expected = linear_scan(&products, |px, py| {
+                    (x - px) * (x - px) + (y - py) * (y - py) < radius * radius
+                });
+                assert_eq!(index.find_within(x, y, radius), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn find_along_segment_matches_a_linear_scan() {
+        let config = StoreConfig::from_buffer(&[]);
+        let mut rng = Rng::new(11);
+        let (index, products) = random_products(&mut rng, &config);
+
+        for _ in 0..500 {
+            let a = random_point(&mut rng, &config);
+            let b = (
+                a.0 + (rng.next_f32() - 0.5) * 30.0,
+                a.1 + (rng.next_f32() - 0.5) * 30.0,
+            );
+            let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+            let len_sq = dx * dx + dy * dy;
+            let expected = linear_scan(&products, |px, py| {
+                let t = (((px - a.0) * dx + (py - a.1) * dy) / len_sq).clamp(0.0, 1.0);
+                let (ex, ey) = (a.0 + dx * t - px, a.1 + dy * t - py);
+                ex * ex + ey * ey < PRODUCT_CLEARANCE * PRODUCT_CLEARANCE
+            });
+            assert_eq!(index.find_along_segment(a, b, PRODUCT_CLEARANCE), expected);
+        }
+    }
+}
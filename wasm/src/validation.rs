@@ -0,0 +1,212 @@
+use crate::constants::{NAV_CELL_SIZE, StoreConfig};
+use crate::grid::{MAX_NAV_GRID_SIZE, build_nav_grid};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// A named inconsistency in a store layout, e.g. rows that run past the store edge
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigProblem {
+    code: &'static str,
+    message: String,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl ConfigProblem {
+    /// Stable camelCase identifier, e.g. `rowsPastStoreWidth`
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn code(&self) -> String {
+        self.code.to_string()
+    }
+
+    /// Human-readable description with the offending values
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+}
+
+impl ConfigProblem {
+    fn new(code: &'static str, message: String) -> Self {
+        ConfigProblem { code, message }
+    }
+}
+
+impl StoreConfig {
+    /// Check the layout for values that make the nav grid useless; an empty list means the
+    /// config is consistent
+    pub fn validate(&self) -> Vec<ConfigProblem> {
+        let mut problems = Vec::new();
+
+        let size_is_valid = self.store_width.is_finite()
+            && self.store_height.is_finite()
+            && self.store_width > 0.0
+            && self.store_height > 0.0;
+        if !size_is_valid {
+            problems.push(ConfigProblem::new(
+                "invalidStoreSize",
+                format!(
+                    "store size {} x {} must be positive",
+                    self.store_width, self.store_height
+                ),
+            ));
+            // Every other check is relative to the store size
+            return problems;
+        }
+
+        let max_extent = MAX_NAV_GRID_SIZE as f32 * NAV_CELL_SIZE;
+        if self.store_width > max_extent || self.store_height > max_extent {
+            problems.push(ConfigProblem::new(
+                "storeTooLarge",
+                format!(
+                    "store size {} x {} exceeds the largest nav grid, {max_extent} on each side",
+                    self.store_width, self.store_height
+                ),
+            ));
+            return problems;
+        }
+
+        if self.row_count < 1 {
+            problems.push(ConfigProblem::new(
+                "noRows",
+                format!("row count {} must be at least 1", self.row_count),
+            ));
+        }
+        if self.row_thickness <= 0.0 {
+            problems.push(ConfigProblem::new(
+                "invalidRowThickness",
+                format!("row thickness {} must be positive", self.row_thickness),
+            ));
+        }
+        if self.row_count > 1 && self.row_spacing <= self.row_thickness {
+            problems.push(ConfigProblem::new(
+                "rowSpacingTooSmall",
+                format!(
+                    "row spacing {} leaves no walkway between rows {} thick",
+                    self.row_spacing, self.row_thickness
+                ),
+            ));
+        }
+
+        if self.row_count >= 1 {
+            let half_width = self.store_width / 2.0;
+            let first_edge = self.get_row_center(0) - self.row_thickness / 2.0;
+            let last_edge = self.get_row_center(self.row_count - 1) + self.row_thickness / 2.0;
+            if first_edge < -half_width || last_edge > half_width {
+                problems.push(ConfigProblem::new(
+                    "rowsPastStoreWidth",
+                    format!(
+                        "rows span x = {first_edge} to {last_edge}, outside the store width {}",
+                        self.store_width
+                    ),
+                ));
+            }
+        }
+
+        let cross_row_width = self.row_spacing - self.row_thickness - self.cross_row_buffer;
+        if self.row_count > 1 && self.row_spacing > self.row_thickness && cross_row_width <= 0.0 {
+            problems.push(ConfigProblem::new(
+                "crossRowBufferTooLarge",
+                format!(
+                    "cross-row buffer {} leaves no walkway between rows",
+                    self.cross_row_buffer
+                ),
+            ));
+        }
+
+        if self.walkway_width <= 0.0 || self.walkway_width > self.store_height / 2.0 {
+            problems.push(ConfigProblem::new(
+                "invalidWalkwayWidth",
+                format!(
+                    "walkway width {} must be positive and at most half the store height {}",
+                    self.walkway_width, self.store_height
+                ),
+            ));
+        }
+
+        if self.outer_walkway_offset < 0.0 || self.outer_walkway_offset > self.store_height / 2.0 {
+            problems.push(ConfigProblem::new(
+                "outerWalkwayOutsideStore",
+                format!(
+                    "outer walkway offset {} puts the outer walkway outside the store",
+                    self.outer_walkway_offset
+                ),
+            ));
+        }
+
+        let grid = build_nav_grid(self);
+        let walkable = (0..grid.rows())
+            .flat_map(|row| (0..grid.cols()).map(move |col| (col, row)))
+            .any(|(col, row)| grid.is_walkable(col, row));
+        if !walkable {
+            problems.push(ConfigProblem::new(
+                "noWalkableCells",
+                "the rows cover the whole store, leaving no walkable cells".to_string(),
+            ));
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(config: &StoreConfig) -> Vec<String> {
+        config.validate().iter().map(ConfigProblem::code).collect()
+    }
+
+    /// Default layout with one change applied
+    fn changed(change: impl FnOnce(&mut StoreConfig)) -> StoreConfig {
+        let mut config = StoreConfig::from_buffer(&[]);
+        change(&mut config);
+        config
+    }
+
+    #[test]
+    fn the_default_layout_is_valid() {
+        assert!(StoreConfig::from_buffer(&[]).validate().is_empty());
+    }
+
+    #[test]
+    fn each_invalid_field_is_reported_by_its_code() {
+        let cases: [(&str, StoreConfig); 9] = [
+            ("invalidStoreSize", changed(|c| c.store_width = 0.0)),
+            ("storeTooLarge", changed(|c| c.store_height = 1.0e6)),
+            ("noRows", changed(|c| c.row_count = 0)),
+            ("invalidRowThickness", changed(|c| c.row_thickness = 0.0)),
+            ("rowSpacingTooSmall", changed(|c| c.row_spacing = 5.0)),
+            ("rowsPastStoreWidth", changed(|c| c.row_count = 10)),
+            (
+                "crossRowBufferTooLarge",
+                changed(|c| c.cross_row_buffer = 40.0),
+            ),
+            ("invalidWalkwayWidth", changed(|c| c.walkway_width = 0.0)),
+            (
+                "outerWalkwayOutsideStore",
+                changed(|c| c.outer_walkway_offset = -1.0),
+            ),
+        ];
+        for (code, config) in cases {
+            assert_eq!(codes(&config), [code]);
+        }
+    }
+
+    #[test]
+    fn a_shelf_covering_the_whole_store_leaves_no_walkable_cells() {
+        // One centred row as wide as the store, which no single field can express
+        let config = changed(|c| {
+            c.row_count = 1;
+            c.start_offset = c.store_width / 2.0;
+            c.row_thickness = c.store_width - 5.0;
+        });
+        assert_eq!(codes(&config), ["noWalkableCells"]);
+    }
+
+    #[test]
+    fn problems_carry_the_offending_values() {
+        let problems = changed(|c| c.walkway_width = -3.0).validate();
+        assert!(problems[0].message().contains("-3"));
+    }
+}